@@ -25,7 +25,7 @@ fn bench_parse_document(c: &mut Criterion) {
 fn bench_wrap_long_lines(c: &mut Criterion) {
     let long_lines = include_str!("long_document.input")
         .split("\n")
-        .map(|line| RawLine::from_string(line))
+        .map(RawLine::from_string)
         .map(|raw_line| FormattedLine::from_raw(raw_line, 0))
         .collect::<Vec<FormattedLine>>();
 
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::clock::{Clock, SystemClock};
+use crate::{consts, LineEnding, Syntax};
+
+/// What to do with a fenced filetype block that is missing its closing marker
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum UnclosedFencePolicy {
+    /// Report the block but format the document as is, which means all following lines are
+    /// considered to be part of the block
+    #[default]
+    Warn,
+    /// Close the block at the next header, or at the end of the document
+    CloseAtHeader,
+    /// Report the block as an error and leave the document unformatted
+    Refuse,
+}
+
+/// How blocks of preformatted text are written
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum PreformattedStyle {
+    /// Leave preformatted lines and fenced filetype blocks as they are
+    #[default]
+    Keep,
+    /// Turn runs of preformatted lines (`| `) into fenced filetype blocks
+    Fenced,
+    /// Turn fenced filetype blocks that don't have a filetype into preformatted lines (`| `)
+    Prefixed,
+}
+
+/// What happens to the whitespace at the end of the lines of fenced filetype blocks
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum PreformattedWhitespace {
+    /// Keep trailing whitespace, and keep lines with only whitespace exactly as they are
+    #[default]
+    Preserve,
+    /// Remove trailing whitespace, which turns lines with only whitespace into blank lines
+    StripTrailing,
+    /// Like `StripTrailing`, and also shorten runs of blank lines to a single blank line
+    CollapseBlankLines,
+}
+
+/// How the indenting of lines is written
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum IndentStyle {
+    /// `INDENT_SHIFT` spaces per level
+    #[default]
+    Spaces,
+    /// One tab per level
+    Tabs,
+}
+
+/// Options that change how a document is formatted. `Config::default()` results in the same
+/// formatting as `format()`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Lines longer than this are wrapped at the nearest preceding whitespace
+    pub max_line_length: usize,
+    /// When set, checked TODO items (`[x]`) get this marker followed by the date of completion
+    /// appended to them, e.g. `✓2026-10-17`. The stamp is removed again when the item is
+    /// unchecked.
+    pub completion_stamp: Option<String>,
+    /// Provides the date used for completion stamps
+    pub clock: Arc<dyn Clock>,
+    /// Moves checked TODO items below the unchecked items of the same list
+    pub sort_todo_items: bool,
+    /// Aligns the columns of tables made of preformatted lines, like `| name | owner |`
+    pub align_tables: bool,
+    /// Puts the number of every header in front of its title, like `=== 2.1. Title`
+    pub number_headers: bool,
+    /// Maps the language of fenced filetype blocks (e.g. `rust`) to a command that reformats
+    /// their contents, e.g. `rustfmt --emit stdout`. The contents are passed to the command's
+    /// stdin and are replaced with whatever it writes to stdout.
+    pub fence_formatters: BTreeMap<String, String>,
+    /// What happens to a fenced filetype block without a closing marker, which is reported as a
+    /// warning unless the policy is `Refuse`
+    pub unclosed_fence_policy: UnclosedFencePolicy,
+    /// When not empty, fenced filetype blocks with a filetype that is not in this list are
+    /// reported
+    pub known_fence_languages: Vec<String>,
+    /// Lowercases the filetypes of fenced filetype blocks and replaces aliases (see
+    /// `fence_language_aliases`) with the language they stand for
+    pub normalize_fence_languages: bool,
+    /// Maps aliases of fenced filetypes (e.g. `py`) to the language they stand for (`python`)
+    pub fence_language_aliases: BTreeMap<String, String>,
+    /// Converts runs of preformatted lines and fenced filetype blocks into each other, or keeps
+    /// them as they are
+    pub preformatted_style: PreformattedStyle,
+    /// The prefixes that mark headers, bullet points, etc.
+    pub syntax: Syntax,
+    /// Number of columns a tab in the indenting of a line moves to, which matters for files that
+    /// mix tabs and spaces
+    pub tab_width: usize,
+    /// Keeps the tabs in the indenting of the lines of fenced filetype blocks instead of replacing
+    /// them with spaces
+    pub preserve_preformatted_tabs: bool,
+    /// Keeps or strips the trailing whitespace and blank lines of fenced filetype blocks
+    pub preformatted_whitespace: PreformattedWhitespace,
+    /// Indents lines with spaces (`INDENT_SHIFT` per level) or with tabs
+    pub indent_style: IndentStyle,
+    /// The line ending of the formatted text. When `None`, the line ending that is used most in
+    /// the text that is formatted is kept.
+    pub line_ending: Option<LineEnding>,
+    /// Ends the formatted text with a line ending. There is never more than one at the end.
+    pub final_newline: bool,
+    /// The number of blank lines before a header, by the indent level of the header. Headers
+    /// that are nested deeper than this list is long use its last entry.
+    pub blank_lines_before_headers: Vec<usize>,
+    /// The number of blank lines after a header, by the indent level of the header, like
+    /// `blank_lines_before_headers`
+    pub blank_lines_after_headers: Vec<usize>,
+    /// Separates a header without any contents from the header that follows it with blank lines.
+    /// Without it the headers are placed directly below each other.
+    pub blank_line_after_empty_headers: bool,
+    /// Keeps blank lines between the items of a list, otherwise they are removed
+    pub blank_lines_between_list_items: bool,
+    /// Runs of blank lines between lines of text are shortened to this number of blank lines
+    pub max_consecutive_blank_lines: usize,
+}
+
+impl Config {
+    /// Returns the number of blank lines that are placed before a header at `indent_level`
+    pub fn blank_lines_before_header(&self, indent_level: usize) -> usize {
+        blank_lines_for_level(&self.blank_lines_before_headers, indent_level)
+    }
+
+    /// Returns the number of blank lines that are placed after a header at `indent_level`
+    pub fn blank_lines_after_header(&self, indent_level: usize) -> usize {
+        blank_lines_for_level(&self.blank_lines_after_headers, indent_level)
+    }
+}
+
+fn blank_lines_for_level(blank_lines_by_level: &[usize], indent_level: usize) -> usize {
+    blank_lines_by_level
+        .get(indent_level)
+        .or(blank_lines_by_level.last())
+        .copied()
+        .unwrap_or(0)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_line_length: consts::MAX_LINE_LENGTH,
+            completion_stamp: None,
+            clock: Arc::new(SystemClock),
+            sort_todo_items: false,
+            align_tables: false,
+            number_headers: false,
+            fence_formatters: BTreeMap::new(),
+            unclosed_fence_policy: UnclosedFencePolicy::default(),
+            known_fence_languages: Vec::new(),
+            normalize_fence_languages: false,
+            fence_language_aliases: consts::FENCE_LANGUAGE_ALIASES
+                .iter()
+                .map(|&(alias, language)| (alias.to_owned(), language.to_owned()))
+                .collect(),
+            preformatted_style: PreformattedStyle::default(),
+            syntax: Syntax::default(),
+            tab_width: consts::TAB_WIDTH,
+            preserve_preformatted_tabs: false,
+            preformatted_whitespace: PreformattedWhitespace::default(),
+            indent_style: IndentStyle::default(),
+            line_ending: None,
+            final_newline: true,
+            blank_lines_before_headers: vec![1],
+            blank_lines_after_headers: vec![1],
+            blank_line_after_empty_headers: true,
+            blank_lines_between_list_items: true,
+            max_consecutive_blank_lines: 1,
+        }
+    }
+}
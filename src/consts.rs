@@ -1,5 +1,8 @@
 pub const INDENT_SHIFT: usize = 4;
 pub const MAX_LINE_LENGTH: usize = 119;
+pub const BYTE_ORDER_MARK: char = '\u{feff}';
+/// Number of columns a tab in the indenting of a line moves to
+pub const TAB_WIDTH: usize = 4;
 
 pub const PREFIX_BULLET_POINT: &str = "* ";
 pub const PREFIX_HEADER: &str = "=== ";
@@ -10,3 +13,28 @@ pub const PREFIX_TODO_ITEM: &str = "[";
 
 pub const MARKER_FENCED_FILETYPE_BACKTICK: &str = "```";
 pub const MARKER_FENCED_FILETYPE_TILDE: &str = "~~~";
+
+/// Default aliases of the filetypes of fenced filetype blocks, and the language they stand for
+pub const FENCE_LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("js", "javascript"),
+    ("md", "markdown"),
+    ("py", "python"),
+    ("rs", "rust"),
+    ("ts", "typescript"),
+    ("yml", "yaml"),
+];
+
+pub const MARKER_COMPLETION_STAMP: &str = "✓";
+
+/// The stylesheet that is embedded in exported HTML documents by default
+pub const HTML_STYLESHEET: &str = "\
+body { font-family: sans-serif; line-height: 1.5; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+details { margin-left: 1em; }
+body > details { margin-left: 0; }
+summary { font-weight: bold; cursor: pointer; margin: 0.5em 0; }
+ul { padding-left: 1.5em; }
+li.todo { list-style: none; margin-left: -1.5em; }
+li.todo.checked { color: gray; text-decoration: line-through; }
+blockquote { border-left: 3px solid lightgray; margin-left: 0; padding-left: 1em; color: dimgray; }
+pre { background: whitesmoke; padding: 0.5em; overflow-x: auto; }
+";
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::PreformattedWhitespace;
+use crate::line_ending::split_lines_with_endings;
+use crate::{consts, parse_document_with_config, Config, LineType};
+
+/// A range of the parsed text, in bytes and in (1-based) line numbers
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Span {
+    /// Offset of the first byte
+    pub start: usize,
+    /// Offset of the byte after the last byte
+    pub end: usize,
+    pub first_line: usize,
+    pub last_line: usize,
+}
+
+impl Span {
+    fn new(start: usize, end: usize, line_number: usize) -> Self {
+        Span {
+            start,
+            end,
+            first_line: line_number,
+            last_line: line_number,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns whether the byte at `offset` is part of the span
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum NodeKind {
+    Document,
+    /// A header line, the lines below it and the sections nested under it
+    Section,
+    /// A single line, including its line ending
+    Line(LineType),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum TokenKind {
+    ByteOrderMark,
+    /// The whitespace at the start of a line
+    Indent,
+    /// The text of a line, from its first to its last non-whitespace character
+    Text,
+    TrailingWhitespace,
+    LineEnding,
+}
+
+/// A piece of the parsed text that isn't split up any further
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+impl Element {
+    pub fn span(&self) -> Span {
+        match self {
+            Element::Node(node) => node.span,
+            Element::Token(token) => token.span,
+        }
+    }
+}
+
+/// A node of a `SyntaxTree`, which spans the text of all its children
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Node {
+    kind: NodeKind,
+    span: Span,
+    children: Vec<Element>,
+}
+
+impl Node {
+    fn new(kind: NodeKind, span: Span) -> Self {
+        Node {
+            kind,
+            span,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn children(&self) -> &[Element] {
+        &self.children
+    }
+
+    /// Returns the tokens of the node and all nodes below it, in the order of the text
+    pub fn tokens(&self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        for child in self.children.iter() {
+            match child {
+                Element::Node(node) => tokens.extend(node.tokens()),
+                Element::Token(token) => tokens.push(*token),
+            }
+        }
+
+        tokens
+    }
+
+    /// Returns the deepest node below this one (or this node itself) that contains the byte at
+    /// `offset`
+    pub fn node_at(&self, offset: usize) -> Option<&Node> {
+        if !self.span.contains(offset) {
+            return None;
+        }
+
+        self.children
+            .iter()
+            .filter_map(|child| match child {
+                Element::Node(node) => node.node_at(offset),
+                Element::Token(_) => None,
+            })
+            .next()
+            .or(Some(self))
+    }
+
+    fn push(&mut self, element: Element) {
+        let span = element.span();
+        if self.children.is_empty() {
+            self.span = span;
+        } else {
+            self.span.end = span.end;
+            self.span.last_line = span.last_line;
+        }
+
+        self.children.push(element);
+    }
+}
+
+/// A lossless representation of a parsed text: every byte of the text is part of exactly one
+/// token, so printing the tree (with `to_string()`) results in the parsed text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyntaxTree {
+    source: String,
+    root: Node,
+}
+
+impl SyntaxTree {
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// The text that was parsed
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the parsed text that `span` covers
+    pub fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.root.tokens() {
+            f.write_str(self.text(token.span))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `contents` into a lossless `SyntaxTree`.
+pub fn parse_syntax_tree(contents: &str) -> SyntaxTree {
+    parse_syntax_tree_with_config(contents, &Config::default())
+}
+
+/// Like `parse_syntax_tree()`, but determines the types of lines like
+/// `parse_document_with_config()` does. Options that change the parsed lines (like collapsing
+/// blank lines) are ignored, since the tree describes the text as it is.
+pub fn parse_syntax_tree_with_config(contents: &str, config: &Config) -> SyntaxTree {
+    let config = Config {
+        preformatted_whitespace: PreformattedWhitespace::Preserve,
+        ..config.clone()
+    };
+    let document = parse_document_with_config(contents, &config);
+
+    // The type of every parsed line, and for headers the depth of their section
+    let mut line_types = HashMap::new();
+    for block in document.blocks.iter() {
+        if let Some(header) = block.header() {
+            if let Some(line_number) = header.original_raw().line_number() {
+                line_types.insert(
+                    line_number,
+                    (LineType::Header, Some(header.indent_level() + 1)),
+                );
+            }
+        }
+
+        for line in block.contents() {
+            // Lines that were added while parsing, like closing markers, have no line number
+            if let Some(line_number) = line.original_raw().line_number() {
+                line_types.insert(line_number, (line.line_type(), None));
+            }
+        }
+    }
+
+    let mut open_nodes = vec![Node::new(NodeKind::Document, Span::new(0, 0, 1))];
+    let mut offset = 0;
+    if document.has_byte_order_mark {
+        offset = consts::BYTE_ORDER_MARK.len_utf8();
+        open_nodes[0].push(Element::Token(Token {
+            kind: TokenKind::ByteOrderMark,
+            span: Span::new(0, offset, 1),
+        }));
+    }
+
+    for (index, (line, line_ending)) in split_lines_with_endings(&contents[offset..]).enumerate() {
+        let line_number = index + 1;
+        let (line_type, section_depth) = line_types
+            .get(&line_number)
+            .copied()
+            .unwrap_or((LineType::Text, None));
+
+        if let Some(depth) = section_depth {
+            while open_nodes.len() > depth {
+                close_last_node(&mut open_nodes);
+            }
+
+            open_nodes.push(Node::new(
+                NodeKind::Section,
+                Span::new(offset, offset, line_number),
+            ));
+        }
+
+        let line_node = line_node(line, line_ending, line_type, offset, line_number);
+        offset = line_node.span.end;
+        open_nodes
+            .last_mut()
+            .expect("the document node should never be closed")
+            .push(Element::Node(line_node));
+    }
+
+    while open_nodes.len() > 1 {
+        close_last_node(&mut open_nodes);
+    }
+
+    SyntaxTree {
+        source: contents.to_owned(),
+        root: open_nodes.remove(0),
+    }
+}
+
+fn close_last_node(open_nodes: &mut Vec<Node>) {
+    let node = open_nodes.pop().expect("there should be a node to close");
+    open_nodes
+        .last_mut()
+        .expect("the document node should never be closed")
+        .push(Element::Node(node));
+}
+
+/// Splits a line into its tokens. `offset` is the position of the line in the parsed text.
+fn line_node(
+    line: &str,
+    line_ending: &str,
+    line_type: LineType,
+    offset: usize,
+    line_number: usize,
+) -> Node {
+    let indent_length = line.len() - line.trim_start().len();
+    let text_length = line.trim().len();
+    let trailing_length = line.len() - indent_length - text_length;
+
+    let mut node = Node::new(
+        NodeKind::Line(line_type),
+        Span::new(offset, offset, line_number),
+    );
+    let mut start = offset;
+    for (kind, length) in [
+        (TokenKind::Indent, indent_length),
+        (TokenKind::Text, text_length),
+        (TokenKind::TrailingWhitespace, trailing_length),
+        (TokenKind::LineEnding, line_ending.len()),
+    ] {
+        if length == 0 {
+            continue;
+        }
+
+        node.push(Element::Token(Token {
+            kind,
+            span: Span::new(start, start + length, line_number),
+        }));
+        start += length;
+    }
+
+    node
+}
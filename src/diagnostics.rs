@@ -0,0 +1,60 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while parsing or formatting a document, which is reported to the user instead
+/// of silently being ignored.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The (1-based) number of the line in the input the problem relates to, if any
+    pub line_number: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic with a severity that depends on the options, like that of an unclosed
+    /// fence
+    pub fn new(severity: Severity, line_number: Option<usize>, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            line_number,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(line_number: Option<usize>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, line_number, message)
+    }
+
+    pub fn error(line_number: Option<usize>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, line_number, message)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line_number) = self.line_number {
+            write!(f, "line {}: ", line_number)?;
+        }
+
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::config::PreformattedStyle;
+use crate::diagnostics::Diagnostic;
+use crate::{consts, Config, FormattedLine, LineType, Prefix, RawLine};
+
+/// The position of a fenced filetype block within a list of lines
+struct FencedBlock {
+    /// Index of the line with the opening marker
+    start: usize,
+    /// Index of the line with the closing marker, if the block was closed
+    end: Option<usize>,
+}
+
+impl FencedBlock {
+    fn body_range(&self, num_lines: usize) -> std::ops::Range<usize> {
+        self.start + 1..self.end.unwrap_or(num_lines)
+    }
+}
+
+/// Pipes the contents of each fenced filetype block through the command configured for the
+/// block's language in `formatters` and replaces the contents with the command's output.
+///
+/// Commands are split at whitespace into the program and its arguments, no shell is involved. If
+/// a command can't be run, exits with a failure status or returns something that isn't UTF-8 the
+/// block is left as it was and a diagnostic is returned.
+pub fn run_fence_formatters(
+    formatted_lines: &mut Vec<FormattedLine>,
+    formatters: &BTreeMap<String, String>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    // Going through the blocks in reverse keeps the positions of the remaining blocks valid when
+    // the number of lines of a block changes
+    for fenced_block in find_fenced_blocks(formatted_lines).into_iter().rev() {
+        // Leave unclosed blocks alone, their contents are unreliable
+        if fenced_block.end.is_none() {
+            continue;
+        }
+
+        let opening_line = &formatted_lines[fenced_block.start];
+        let Some(command) = formatters.get(fence_language(&opening_line.contents)) else {
+            continue;
+        };
+
+        let body_range = fenced_block.body_range(formatted_lines.len());
+        let body = formatted_lines[body_range.clone()]
+            .iter()
+            .map(|line| format!("{}\n", line.contents))
+            .collect::<String>();
+
+        match run_formatter(command, &body) {
+            Ok(formatted_body) => {
+                let indent_level = opening_line.indent_level;
+                let new_lines = formatted_body
+                    .lines()
+                    .map(|line| FormattedLine {
+                        contents: line.trim_end().to_owned(),
+                        indent_level,
+                        line_type: LineType::FencedFiletype,
+                        original_raw: RawLine::default(),
+                    })
+                    .collect::<Vec<FormattedLine>>();
+
+                formatted_lines.splice(body_range, new_lines);
+            }
+            Err(message) => diagnostics.push(Diagnostic::warning(
+                opening_line.original_raw.line_number,
+                format!("fenced block was not reformatted: {}", message),
+            )),
+        }
+    }
+
+    // Report the diagnostics in the order of the document
+    diagnostics.reverse();
+
+    diagnostics
+}
+
+/// Normalizes the filetype of fenced filetype blocks (if enabled in `config`) and reports the
+/// blocks whose filetype is not one of the known languages.
+///
+/// Normalizing lowercases the filetype and replaces aliases with the language they stand for,
+/// e.g. "``` Py" becomes "```python".
+pub fn check_fence_languages(
+    formatted_lines: &mut [FormattedLine],
+    config: &Config,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for fenced_block in find_fenced_blocks(formatted_lines) {
+        let opening_line = &mut formatted_lines[fenced_block.start];
+
+        if config.normalize_fence_languages {
+            opening_line.contents =
+                normalize_info_string(&opening_line.contents, &config.fence_language_aliases);
+        }
+
+        let language = fence_language(&opening_line.contents);
+        if !language.is_empty()
+            && !config.known_fence_languages.is_empty()
+            && !config
+                .known_fence_languages
+                .iter()
+                .any(|known_language| known_language == language)
+        {
+            diagnostics.push(Diagnostic::warning(
+                opening_line.original_raw.line_number,
+                format!("unknown fenced filetype `{}`", language),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// Converts runs of preformatted lines (`| `) into fenced filetype blocks, or fenced filetype
+/// blocks without a filetype into preformatted lines, depending on the configured
+/// `preformatted_style`.
+pub fn convert_preformatted_style(formatted_lines: &mut Vec<FormattedLine>, config: &Config) {
+    let prefix = &config.syntax.preformatted;
+
+    match config.preformatted_style {
+        PreformattedStyle::Keep => {}
+        PreformattedStyle::Fenced => convert_preformatted_to_fenced(formatted_lines, prefix),
+        PreformattedStyle::Prefixed => convert_fenced_to_preformatted(formatted_lines, prefix),
+    }
+}
+
+fn convert_preformatted_to_fenced(formatted_lines: &mut Vec<FormattedLine>, prefix: &Prefix) {
+    let mut index = 0;
+    while index < formatted_lines.len() {
+        let indent_level = formatted_lines[index].indent_level;
+        let run_length = formatted_lines[index..]
+            .iter()
+            .take_while(|line| {
+                line.line_type == LineType::Preformatted && line.indent_level == indent_level
+            })
+            .count();
+
+        if run_length == 0 {
+            index += 1;
+            continue;
+        }
+
+        let body = formatted_lines[index..index + run_length]
+            .iter()
+            .map(|line| FormattedLine {
+                contents: strip_preformatted_prefix(&line.contents, prefix).to_owned(),
+                line_type: LineType::FencedFiletype,
+                ..line.clone()
+            })
+            .collect::<Vec<FormattedLine>>();
+
+        // The marker has to be longer than any run of backticks that a line of the body starts
+        // with (after its indenting), otherwise the body would close the block
+        let marker_length = body
+            .iter()
+            .map(|line| {
+                let contents = line.contents.trim_start();
+                contents.chars().take_while(|&c| c == '`').count() + 1
+            })
+            .max()
+            .unwrap_or(0)
+            .max(consts::MARKER_FENCED_FILETYPE_BACKTICK.len());
+        let marker_line = FormattedLine {
+            contents: "`".repeat(marker_length),
+            indent_level,
+            line_type: LineType::FencedFiletypeMarker,
+            original_raw: RawLine::default(),
+        };
+
+        let fenced_lines = std::iter::once(marker_line.clone())
+            .chain(body)
+            .chain(std::iter::once(marker_line));
+        formatted_lines.splice(index..index + run_length, fenced_lines);
+
+        index += run_length + 2;
+    }
+}
+
+fn convert_fenced_to_preformatted(formatted_lines: &mut Vec<FormattedLine>, prefix: &Prefix) {
+    for fenced_block in find_fenced_blocks(formatted_lines).into_iter().rev() {
+        // Converting a block with a filetype would lose its syntax highlighting, and the
+        // contents of an unclosed block are unreliable
+        let Some(end) = fenced_block.end else {
+            continue;
+        };
+        if !fence_language(&formatted_lines[fenced_block.start].contents).is_empty() {
+            continue;
+        }
+
+        let indent_level = formatted_lines[fenced_block.start].indent_level;
+        let preformatted_lines = formatted_lines[fenced_block.start + 1..end]
+            .iter()
+            .map(|line| FormattedLine {
+                contents: format!("{}{}", prefix.canonical, line.contents)
+                    .trim_end()
+                    .to_owned(),
+                indent_level,
+                line_type: LineType::Preformatted,
+                ..line.clone()
+            })
+            .collect::<Vec<FormattedLine>>();
+
+        formatted_lines.splice(fenced_block.start..=end, preformatted_lines);
+    }
+}
+
+/// Removes the prefix from a preformatted line. Its prefix is always the canonical one, since
+/// alternative prefixes are replaced while parsing.
+fn strip_preformatted_prefix<'a>(contents: &'a str, prefix: &Prefix) -> &'a str {
+    contents
+        .strip_prefix(prefix.canonical.as_str())
+        .unwrap_or_else(|| contents.trim_start_matches(prefix.canonical.trim_end()))
+}
+
+/// Lowercases the filetype of a marker line and replaces an alias with the language it stands
+/// for. Anything following the filetype is kept.
+fn normalize_info_string(marker_line: &str, aliases: &BTreeMap<String, String>) -> String {
+    let info_start = marker_line
+        .find(|c| c != '`' && c != '~')
+        .unwrap_or(marker_line.len());
+    let (marker, info_string) = marker_line.split_at(info_start);
+
+    let mut words = info_string.split_whitespace();
+    let Some(language) = words.next() else {
+        return marker.to_owned();
+    };
+
+    let language = language.to_lowercase();
+    let language = aliases.get(&language).cloned().unwrap_or(language);
+    let rest = words.collect::<Vec<&str>>().join(" ");
+
+    if rest.is_empty() {
+        format!("{}{}", marker, language)
+    } else {
+        format!("{}{} {}", marker, language, rest)
+    }
+}
+
+fn find_fenced_blocks(formatted_lines: &[FormattedLine]) -> Vec<FencedBlock> {
+    let mut fenced_blocks: Vec<FencedBlock> = Vec::new();
+
+    for (index, line) in formatted_lines.iter().enumerate() {
+        if line.line_type != LineType::FencedFiletypeMarker {
+            continue;
+        }
+
+        match fenced_blocks.last_mut() {
+            Some(fenced_block) if fenced_block.end.is_none() => fenced_block.end = Some(index),
+            _ => fenced_blocks.push(FencedBlock {
+                start: index,
+                end: None,
+            }),
+        }
+    }
+
+    fenced_blocks
+}
+
+/// Returns the language of a fenced filetype block (e.g. `rust` for "```rust"), which is the
+/// first word after the marker.
+pub(crate) fn fence_language(marker_line: &str) -> &str {
+    marker_line
+        .trim_start_matches(['`', '~'])
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+}
+
+fn run_formatter(command: &str, input: &str) -> Result<String, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("no command given")?;
+
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("could not run `{}`: {}", command, err))?;
+
+    // Writing happens on a separate thread so that a command that outputs a lot before having
+    // read all of its input can't cause a deadlock
+    let mut stdin = child.stdin.take().expect("stdin should be piped");
+    let input = input.to_owned();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("could not run `{}`: {}", command, err))?;
+    // A failed write shows up as a failure of the command itself, so the result can be ignored
+    let _ = writer.join();
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or("").trim();
+
+        return Err(
+            format!("`{}` failed ({}) {}", command, output.status, reason)
+                .trim_end()
+                .to_owned(),
+        );
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| format!("`{}` did not output UTF-8", command))
+}
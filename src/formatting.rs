@@ -1,15 +1,15 @@
-use crate::{Document, FormattedLine, LineType};
+use crate::clock::Date;
+use crate::config::IndentStyle;
+use crate::{consts, Config, Document, FormattedLine, LineType, Syntax};
 
-#[cfg(not(windows))]
-const LINEBREAK: &str = "\n";
-#[cfg(windows)]
-const LINEBREAK: &str = "\r\n";
-
-#[derive(Debug, Eq, PartialEq)]
+/// The last thing that was written by `format_to_string_with_config()`, which determines the
+/// number of blank lines that go before the next header or line
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum Action {
     Start,
-    InsertBlankLine,
+    InsertHeader { indent_level: usize },
     InsertBodyText,
+    InsertListItem,
 }
 
 /// Splits lines longer than `max_line_length` and inserts a newline at the nearest space preceding
@@ -18,6 +18,21 @@ enum Action {
 /// Because this function modifies the original, already formatted line without any regard for the
 /// indenting the added line needs, the return value indicates if the document needs reformatting.
 pub fn wrap_long_lines(formatted_lines: &mut Vec<FormattedLine>, max_line_length: usize) {
+    let config = Config {
+        max_line_length,
+        ..Config::default()
+    };
+
+    wrap_long_lines_with_config(formatted_lines, &config)
+}
+
+/// Like `wrap_long_lines()`, but takes the maximum line length from `config` and uses its prefixes
+/// for the lines that are added. A tab in a line counts as `config.tab_width` characters.
+pub fn wrap_long_lines_with_config(formatted_lines: &mut Vec<FormattedLine>, config: &Config) {
+    let max_line_length = config.max_line_length;
+    let syntax = &config.syntax;
+    let tab_width = config.tab_width;
+
     // This is a for loop instead of a 'real' loop to guard against any infinite loops. The '100'
     // is chosen arbitrarily
     let max_iterations = 100;
@@ -32,7 +47,8 @@ pub fn wrap_long_lines(formatted_lines: &mut Vec<FormattedLine>, max_line_length
         }
 
         if formatted_lines.iter().all(|line| {
-            line.line_type == LineType::Preformatted || line.contents.len() <= max_line_length
+            line.line_type.is_preformatted()
+                || line_width(&line.contents, tab_width) <= max_line_length
         }) {
             // Nothing more to be done
             break;
@@ -41,22 +57,24 @@ pub fn wrap_long_lines(formatted_lines: &mut Vec<FormattedLine>, max_line_length
         let mut lines_to_insert: Vec<(usize, FormattedLine)> = Vec::new();
 
         for (index, current_line) in formatted_lines.iter_mut().enumerate() {
-            if current_line.line_type == LineType::Preformatted {
+            if current_line.line_type.is_preformatted() {
                 continue;
             }
 
-            if current_line.contents.len() <= max_line_length {
+            if line_width(&current_line.contents, tab_width) <= max_line_length {
                 continue;
             }
 
             // Find a word boundary to split the string at
-            let Some(split_pos) = find_word_boundary(current_line, max_line_length) else {
+            let max_num_chars =
+                num_chars_within_width(&current_line.contents, max_line_length, tab_width);
+            let Some(split_pos) = find_word_boundary(current_line, max_num_chars, syntax) else {
                 continue;
             };
 
             // This FormattedLine will be placed below (line index + 1) the `current_line` in
             // the document
-            lines_to_insert.push((index + 1, split_line(current_line, split_pos)));
+            lines_to_insert.push((index + 1, split_line(current_line, split_pos, syntax)));
         }
 
         lines_were_changed = Some(!lines_to_insert.is_empty());
@@ -74,59 +92,208 @@ pub fn wrap_long_lines(formatted_lines: &mut Vec<FormattedLine>, max_line_length
     }
 }
 
+/// Adds a completion stamp (`marker` followed by `today`) to the end of every checked TODO item
+/// that doesn't have one yet, and removes the stamp from items that are no longer checked.
+/// Nothing is done when `marker` is empty (or only whitespace), since every date at the end of an
+/// item would then look like a stamp.
+pub fn update_completion_stamps(
+    formatted_lines: &mut Vec<FormattedLine>,
+    marker: &str,
+    today: Date,
+) {
+    if marker.trim().is_empty() {
+        return;
+    }
+
+    let mut index = 0;
+    while index < formatted_lines.len() {
+        if formatted_lines[index].line_type != LineType::ListTodoItem {
+            index += 1;
+            continue;
+        }
+
+        let is_checked = formatted_lines[index].is_checked_todo_item();
+
+        // The stamp belongs at the very end of the item, which can be on a (wrapped) continuation
+        // line
+        let item_indent_level = formatted_lines[index].indent_level;
+        let mut last_index = index;
+        while formatted_lines.get(last_index + 1).map_or(false, |line| {
+            line.line_type == LineType::ListContinuousLine && line.indent_level == item_indent_level
+        }) {
+            last_index += 1;
+        }
+
+        let last_line = &mut formatted_lines[last_index];
+        match (
+            is_checked,
+            find_completion_stamp(&last_line.contents, marker),
+        ) {
+            (true, None) => {
+                last_line.contents = format!("{} {marker}{today}", last_line.contents.trim_end());
+            }
+            (false, Some(stamp_pos)) => {
+                last_line.contents.truncate(stamp_pos);
+
+                if last_line.line_type == LineType::ListContinuousLine
+                    && last_line.contents.trim().is_empty()
+                {
+                    // The stamp was wrapped onto a line of its own
+                    formatted_lines.remove(last_index);
+                    last_index -= 1;
+                }
+            }
+            _ => {}
+        }
+
+        index = last_index + 1;
+    }
+}
+
 pub fn format_to_string(document: &Document) -> String {
+    format_to_string_with_config(document, &Config::default())
+}
+
+/// Like `format_to_string()`, but indents lines according to `config.indent_style` and ends lines
+/// with `config.line_ending`. Without a configured line ending, the one the document was parsed
+/// with is used.
+///
+/// The blank lines around headers and between lines of text follow the blank line options of
+/// `config`. Blank lines at the start and end of the document are left out. The document ends
+/// with exactly one line ending, unless `config.final_newline` is disabled. A byte order mark
+/// that was present in the parsed text is kept.
+pub fn format_to_string_with_config(document: &Document, config: &Config) -> String {
+    let linebreak = config.line_ending.unwrap_or(document.line_ending).as_str();
     let mut formatted = String::new();
     let mut last_action = Action::Start;
+    // Blank lines are only written once it's known what follows them
+    let mut num_blank_lines = 0;
 
     for block in document.blocks.iter() {
-        if last_action == Action::InsertBodyText {
-            formatted += LINEBREAK;
-        }
+        // The text before the first header doesn't have a header to output
+        if block.has_header() {
+            let indent_level = block.header.indent_level;
+            let num_blank_lines_before = match last_action {
+                Action::Start => 0,
+                Action::InsertHeader { .. } if !config.blank_line_after_empty_headers => 0,
+                Action::InsertHeader {
+                    indent_level: previous_indent_level,
+                } => config
+                    .blank_lines_after_header(previous_indent_level)
+                    .max(config.blank_lines_before_header(indent_level)),
+                Action::InsertBodyText | Action::InsertListItem => {
+                    config.blank_lines_before_header(indent_level)
+                }
+            };
 
-        formatted += &format!(
-            "{indenting}{header}{LINEBREAK}{LINEBREAK}",
-            indenting = " ".repeat(block.header.num_indent()),
-            header = block.header.contents
-        );
-        last_action = Action::InsertBlankLine;
+            formatted += &linebreak.repeat(num_blank_lines_before);
+            formatted += &format!(
+                "{indenting}{header}{linebreak}",
+                indenting = indenting(indent_level, config.indent_style),
+                header = block.header.contents
+            );
+            last_action = Action::InsertHeader { indent_level };
+            num_blank_lines = 0;
+        }
 
         for formatted_line in block.contents.iter() {
-            if formatted_line.line_type != LineType::Preformatted
-                && last_action == Action::InsertBlankLine
-                && formatted_line.is_empty()
-            {
-                // Don't output multiple blank lines in a row for non-preformatted lines
+            if formatted_line.is_empty() && !formatted_line.line_type.is_preformatted() {
+                num_blank_lines += 1;
                 continue;
             }
 
-            last_action = if formatted_line.is_empty() {
-                formatted += LINEBREAK;
+            let action = if formatted_line.is_list_item() {
+                Action::InsertListItem
+            } else {
+                Action::InsertBodyText
+            };
+            let num_blank_lines_before = match (last_action, action) {
+                (Action::Start, _) => 0,
+                (Action::InsertHeader { indent_level }, _) => {
+                    config.blank_lines_after_header(indent_level)
+                }
+                (Action::InsertListItem, Action::InsertListItem)
+                    if !config.blank_lines_between_list_items =>
+                {
+                    0
+                }
+                _ => num_blank_lines.min(config.max_consecutive_blank_lines),
+            };
 
-                Action::InsertBlankLine
+            formatted += &linebreak.repeat(num_blank_lines_before);
+            if formatted_line.is_blank() {
+                // A line of a fenced filetype block, which only has whitespace if it is kept as it
+                // was in the parsed text
+                formatted += &formatted_line.contents;
+                formatted += linebreak;
             } else {
                 formatted += &format!(
-                    "{indenting}{line}{LINEBREAK}",
-                    indenting = " ".repeat(formatted_line.num_indent()),
+                    "{indenting}{line}{linebreak}",
+                    indenting = indenting(formatted_line.indent_level, config.indent_style),
                     line = formatted_line.contents
                 );
-                Action::InsertBodyText
-            };
+            }
+            last_action = action;
+            num_blank_lines = 0;
         }
     }
 
-    formatted.trim_start().trim_end_matches(' ').to_owned()
+    // Only the end of the document is trimmed, trimming the start could remove the indenting of
+    // a line that has to be kept as is
+    formatted.truncate(formatted.trim_end().len());
+    if config.final_newline && !formatted.is_empty() {
+        formatted += linebreak;
+    }
+
+    if document.has_byte_order_mark {
+        formatted.insert(0, consts::BYTE_ORDER_MARK);
+    }
+
+    formatted
+}
+
+fn indenting(indent_level: usize, indent_style: IndentStyle) -> String {
+    match indent_style {
+        IndentStyle::Spaces => " ".repeat(indent_level * consts::INDENT_SHIFT),
+        IndentStyle::Tabs => "\t".repeat(indent_level),
+    }
+}
+
+/// Returns the length of a line, where each tab counts as `tab_width` characters.
+fn line_width(contents: &str, tab_width: usize) -> usize {
+    let num_tabs = contents.matches('\t').count();
+
+    contents.len() - num_tabs + num_tabs * tab_width
+}
+
+/// Returns how many characters of `contents` fit within `width`, where each tab counts as
+/// `tab_width` characters.
+fn num_chars_within_width(contents: &str, width: usize, tab_width: usize) -> usize {
+    let mut used_width = 0;
+
+    contents
+        .chars()
+        .take_while(|&c| {
+            used_width += if c == '\t' { tab_width } else { 1 };
+            used_width <= width
+        })
+        .count()
 }
 
 /// Finds a word boundary (i.e. whitespace after a word) nearest to the maximum line length.
-fn find_word_boundary(line: &FormattedLine, max_line_length: usize) -> Option<usize> {
-    let prefix_length = line.line_type.get_prefix_length();
+fn find_word_boundary(
+    line: &FormattedLine,
+    max_line_length: usize,
+    syntax: &Syntax,
+) -> Option<usize> {
+    let prefix_length = line.line_type.get_prefix_length(syntax);
     if let Some(split_pos) = line
         .contents
         .chars()
         // We skip the prefix so that any whitespace in it will not satisfy the
         // `rfind()` below
         .skip(prefix_length)
-        .take(max_line_length - prefix_length + 1)
+        .take((max_line_length + 1).saturating_sub(prefix_length))
         // String allocation is unfortunatly necessary here in order to use `rfind()`
         // because `Take` and `Skip` don't implement the necessary traits for it
         .collect::<String>()
@@ -145,9 +312,39 @@ fn find_word_boundary(line: &FormattedLine, max_line_length: usize) -> Option<us
     }
 }
 
+/// Returns the position of the whitespace preceding a completion stamp (`marker` followed by a
+/// `YYYY-MM-DD` date) at the end of `contents`, if there is one.
+fn find_completion_stamp(contents: &str, marker: &str) -> Option<usize> {
+    const DATE_LENGTH: usize = "YYYY-MM-DD".len();
+
+    let contents = contents.trim_end();
+    let date_start = contents.len().checked_sub(DATE_LENGTH)?;
+    if !contents.is_char_boundary(date_start) {
+        return None;
+    }
+
+    let (before_date, date) = contents.split_at(date_start);
+    let is_date = date.char_indices().all(|(pos, c)| match pos {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    if !is_date {
+        return None;
+    }
+
+    let before_marker = before_date.strip_suffix(marker)?;
+    let before_stamp = before_marker.trim_end();
+    if before_stamp.len() == before_marker.len() && !before_marker.is_empty() {
+        // The stamp has to be a separate word
+        return None;
+    }
+
+    Some(before_stamp.len())
+}
+
 /// Split a line at the specified position, modifying the original line and returning a new
 /// `FormattedLine` with the contents after the split position.
-fn split_line(long_line: &mut FormattedLine, split_pos: usize) -> FormattedLine {
+fn split_line(long_line: &mut FormattedLine, split_pos: usize, syntax: &Syntax) -> FormattedLine {
     let (line_a, line_b) = long_line.contents.split_at(split_pos);
 
     let line_type = if long_line.is_list_item() {
@@ -157,7 +354,7 @@ fn split_line(long_line: &mut FormattedLine, split_pos: usize) -> FormattedLine
     };
 
     let split_line = FormattedLine {
-        contents: format!("{}{}", line_type.get_prefix(), line_b.trim()),
+        contents: format!("{}{}", line_type.get_prefix(syntax), line_b.trim()),
         line_type,
 
         ..long_line.clone()
@@ -0,0 +1,293 @@
+use crate::fences::fence_language;
+use crate::visitor::without_prefix;
+use crate::{consts, walk, Config, Document, FormattedLine, LineType, Syntax, Visitor};
+
+/// The CSS that is embedded in an exported HTML document
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum HtmlStylesheet {
+    /// `consts::HTML_STYLESHEET`
+    #[default]
+    Default,
+    /// CSS that is embedded as is, like the contents of a file
+    Custom(String),
+    /// Don't embed any CSS
+    Omit,
+}
+
+/// The options of `to_html_with_config()` that only matter for HTML
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HtmlOptions {
+    pub stylesheet: HtmlStylesheet,
+}
+
+/// The element that was opened last, which is closed before anything else is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OpenElement {
+    None,
+    Paragraph,
+    Quote,
+    Preformatted,
+    /// The indent levels of the open lists, from the outer to the inner one. Their last item is
+    /// still open, since a nested list goes inside it.
+    List(Vec<usize>),
+}
+
+/// Converts a parsed document to a standalone HTML page. Every header becomes a collapsible
+/// section (a `<details>` element with the title as its `<summary>`) that contains the sections
+/// below it. Bullet points become `<ul>` lists, TODO items disabled checkboxes, fenced filetype
+/// blocks `<pre><code class="language-x">` and runs of preformatted lines `<pre>`.
+pub fn to_html(document: &Document) -> String {
+    to_html_with_config(document, &Config::default(), &HtmlOptions::default())
+}
+
+/// Like `to_html()`, but uses the prefixes of `config.syntax`, embeds `options.stylesheet` and
+/// ends lines like `format_to_string_with_config()` does
+pub fn to_html_with_config(document: &Document, config: &Config, options: &HtmlOptions) -> String {
+    let linebreak = config.line_ending.unwrap_or(document.line_ending).as_str();
+    let mut writer = HtmlWriter {
+        syntax: &config.syntax,
+        linebreak,
+        html: String::new(),
+        open_element: OpenElement::None,
+        open_sections: 0,
+    };
+
+    walk(document, &mut writer);
+    writer.close_element();
+    for _ in 0..writer.open_sections {
+        writer.write_line("</details>");
+    }
+
+    let title = document
+        .blocks
+        .iter()
+        .find_map(|block| block.header())
+        .map(|header| without_prefix(header, &config.syntax).trim())
+        .filter(|title| !title.is_empty())
+        .unwrap_or("Outline");
+
+    let mut head = vec![
+        "<!DOCTYPE html>".to_owned(),
+        "<html>".to_owned(),
+        "<head>".to_owned(),
+        r#"<meta charset="utf-8">"#.to_owned(),
+        format!("<title>{}</title>", escape_html(title)),
+    ];
+    let stylesheet = match &options.stylesheet {
+        HtmlStylesheet::Default => Some(consts::HTML_STYLESHEET),
+        HtmlStylesheet::Custom(stylesheet) => Some(stylesheet.as_str()),
+        HtmlStylesheet::Omit => None,
+    };
+    if let Some(stylesheet) = stylesheet {
+        head.push("<style>".to_owned());
+        head.extend(stylesheet.lines().map(str::to_owned));
+        head.push("</style>".to_owned());
+    }
+    head.extend(["</head>".to_owned(), "<body>".to_owned()]);
+
+    let mut html = head.join(linebreak) + linebreak;
+    html += &writer.html;
+    html += "</body>";
+    html += linebreak;
+    html += "</html>";
+    if config.final_newline {
+        html += linebreak;
+    }
+
+    html
+}
+
+struct HtmlWriter<'a> {
+    syntax: &'a Syntax,
+    linebreak: &'static str,
+    html: String,
+    open_element: OpenElement,
+    /// The number of `<details>` elements that are open, which is the depth of the last header
+    open_sections: usize,
+}
+
+impl HtmlWriter<'_> {
+    fn write_line(&mut self, line: &str) {
+        self.html += line;
+        self.html += self.linebreak;
+    }
+
+    /// Writes the closing tags of the element that is open
+    fn close_element(&mut self) {
+        match std::mem::replace(&mut self.open_element, OpenElement::None) {
+            OpenElement::None => {}
+            OpenElement::Paragraph => self.write_line("</p>"),
+            OpenElement::Quote => self.write_line("</p></blockquote>"),
+            OpenElement::Preformatted => self.write_line("</pre>"),
+            OpenElement::List(indent_levels) => {
+                for _ in indent_levels {
+                    self.write_line("</li>");
+                    self.write_line("</ul>");
+                }
+            }
+        }
+    }
+
+    /// Closes the element that is open and opens `element`, unless it is already open
+    fn open(&mut self, element: OpenElement, opening_tag: &str) {
+        if self.open_element == element {
+            return;
+        }
+
+        self.close_element();
+        self.write_line(opening_tag);
+        self.open_element = element;
+    }
+
+    fn write_list_item(&mut self, lines: &[FormattedLine], opening_tag: &str) {
+        let mut indent_levels = match std::mem::replace(&mut self.open_element, OpenElement::None) {
+            OpenElement::List(indent_levels) => indent_levels,
+            open_element => {
+                self.open_element = open_element;
+                self.close_element();
+                Vec::new()
+            }
+        };
+
+        let indent_level = lines[0].indent_level();
+        while indent_levels
+            .last()
+            .map_or(false, |&level| level > indent_level)
+        {
+            indent_levels.pop();
+            self.write_line("</li>");
+            self.write_line("</ul>");
+        }
+
+        if indent_levels
+            .last()
+            .map_or(true, |&level| level < indent_level)
+        {
+            indent_levels.push(indent_level);
+            self.write_line("<ul>");
+        } else {
+            self.write_line("</li>");
+        }
+
+        let text = std::iter::once(without_prefix(&lines[0], self.syntax))
+            .chain(lines[1..].iter().map(|line| line.contents().trim()))
+            .filter(|text| !text.is_empty())
+            .map(escape_html)
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        self.write_line(&format!("{}{}", opening_tag, text));
+        self.open_element = OpenElement::List(indent_levels);
+    }
+}
+
+impl Visitor for HtmlWriter<'_> {
+    fn visit_header(&mut self, header: &FormattedLine) {
+        self.close_element();
+
+        // Sections at the same or a deeper level than the header end before it
+        let depth = header.indent_level() + 1;
+        while self.open_sections >= depth {
+            self.write_line("</details>");
+            self.open_sections -= 1;
+        }
+
+        let title = escape_html(without_prefix(header, self.syntax).trim());
+        self.write_line(&format!(
+            r#"<details class="level-{}" open><summary>{}</summary>"#,
+            depth, title
+        ));
+        self.open_sections += 1;
+    }
+
+    fn visit_text(&mut self, line: &FormattedLine) {
+        self.open(OpenElement::Paragraph, "<p>");
+        self.write_line(&escape_html(line.contents().trim()));
+    }
+
+    fn visit_blank_line(&mut self, _line: &FormattedLine) {
+        // Lists continue after a blank line, everything else ends
+        if !matches!(self.open_element, OpenElement::List(_)) {
+            self.close_element();
+        }
+    }
+
+    fn visit_list_item(&mut self, lines: &[FormattedLine]) {
+        self.write_list_item(lines, "<li>");
+    }
+
+    fn visit_todo(&mut self, lines: &[FormattedLine], is_checked: bool) {
+        let opening_tag = if is_checked {
+            r#"<li class="todo checked"><input type="checkbox" disabled checked> "#
+        } else {
+            r#"<li class="todo"><input type="checkbox" disabled> "#
+        };
+
+        self.write_list_item(lines, opening_tag);
+    }
+
+    fn visit_fenced_block(&mut self, lines: &[FormattedLine]) {
+        self.close_element();
+
+        // A block that continues after a header has no opening marker, and therefore no language
+        let has_opening_marker = lines[0].line_type() == LineType::FencedFiletypeMarker;
+        let language = if has_opening_marker {
+            fence_language(lines[0].contents())
+        } else {
+            ""
+        };
+        let body_lines = lines
+            .iter()
+            .skip(usize::from(has_opening_marker))
+            .take_while(|line| line.line_type() == LineType::FencedFiletype)
+            .map(|line| escape_html(line.contents()))
+            .collect::<Vec<String>>();
+
+        let opening_tag = if language.is_empty() {
+            "<pre><code>".to_owned()
+        } else {
+            format!(r#"<pre><code class="language-{}">"#, escape_html(language))
+        };
+        // The line breaks inside `<pre>` are part of the code
+        let code = format!(
+            "{}{}</code></pre>",
+            opening_tag,
+            body_lines.join(self.linebreak)
+        );
+        self.write_line(&code);
+    }
+
+    fn visit_preformatted(&mut self, line: &FormattedLine) {
+        if self.open_element == OpenElement::Preformatted {
+            self.html += self.linebreak;
+        } else {
+            self.close_element();
+            self.html += "<pre>";
+            self.open_element = OpenElement::Preformatted;
+        }
+
+        self.html += &escape_html(without_prefix(line, self.syntax));
+    }
+
+    fn visit_quote(&mut self, line: &FormattedLine) {
+        self.open(OpenElement::Quote, "<blockquote><p>");
+        self.write_line(&escape_html(without_prefix(line, self.syntax).trim()));
+    }
+}
+
+/// Replaces the characters that have a meaning in HTML with their character references
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&#39;",
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -1,27 +1,80 @@
+pub mod clock;
 pub mod consts;
+pub mod cst;
 
+mod config;
+mod diagnostics;
+mod fences;
 mod formatting;
+mod html;
+mod line_ending;
+mod markdown;
+mod opml;
+mod outline;
 mod parsing;
-
-pub use formatting::{format_to_string, wrap_long_lines};
-pub use parsing::parse_document;
+mod passes;
+mod syntax;
+mod tables;
+mod tree;
+mod visitor;
+
+pub use config::{
+    Config, IndentStyle, PreformattedStyle, PreformattedWhitespace, UnclosedFencePolicy,
+};
+pub use diagnostics::{Diagnostic, Severity};
+pub use fences::{check_fence_languages, convert_preformatted_style, run_fence_formatters};
+pub use formatting::{
+    format_to_string, format_to_string_with_config, update_completion_stamps, wrap_long_lines,
+    wrap_long_lines_with_config,
+};
+pub use html::{to_html, to_html_with_config, HtmlOptions, HtmlStylesheet};
+pub use line_ending::LineEnding;
+pub use markdown::{
+    from_markdown, from_markdown_with_config, to_markdown, to_markdown_with_config,
+    DeepHeaderStyle, MarkdownOptions,
+};
+pub use opml::{from_opml, from_opml_with_config, to_opml, to_opml_with_config};
+pub use outline::Outline;
+pub use parsing::{parse_document, parse_document_with_config};
+pub use passes::{
+    AlignTables, CheckFenceLanguages, ConvertPreformattedStyle, NumberHeaders, Pass, Pipeline,
+    RunFenceFormatters, SortTodoItems, UpdateCompletionStamps, WrapLongLines,
+};
+pub use syntax::{Prefix, Syntax};
+pub use tables::align_tables;
+pub use tree::{EditError, Node, PreOrder, Section, Tree};
+pub use visitor::{walk, Visitor};
 
 pub fn format(contents: &str) -> String {
-    // Step 1: parse raw lines
-    let mut document = parse_document(contents);
+    format_with_config(contents, &Config::default()).text
+}
 
-    // Step 2: wrapping of long lines
-    for block in document.blocks.iter_mut() {
-        wrap_long_lines(&mut block.contents, consts::MAX_LINE_LENGTH)
-    }
+/// The result of formatting a document
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct Formatted {
+    pub text: String,
+    /// Problems that were encountered while formatting, like external formatters that failed
+    pub diagnostics: Vec<Diagnostic>,
+}
 
-    // Step 3: print formatted lines back into a string, adds extra newlines where needed
-    format_to_string(&document)
+/// Formats `contents` with the passes that are enabled in `config`, see
+/// `Pipeline::from_config()`
+pub fn format_with_config(contents: &str, config: &Config) -> Formatted {
+    Pipeline::from_config(config).format(contents, config)
 }
 
+/// A parsed document, as a list of blocks that each start with a header
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct Document {
     pub blocks: Vec<Block>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// The line ending used by the parsed text, or the native one if it had none
+    pub line_ending: LineEnding,
+    /// Whether the parsed text started with a (UTF-8) byte order mark
+    pub has_byte_order_mark: bool,
 }
 
 impl Document {
@@ -31,6 +84,9 @@ impl Document {
 
         Document {
             blocks: vec![first_block],
+            diagnostics: Vec::new(),
+            line_ending: LineEnding::native(),
+            has_byte_order_mark: false,
         }
     }
 
@@ -64,7 +120,9 @@ impl Default for Document {
     }
 }
 
+/// A header and the lines below it, up to the next header
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     contents: Vec<FormattedLine>,
     header: FormattedLine,
@@ -86,6 +144,21 @@ impl Block {
         self.contents.push(line);
     }
 
+    /// The header of the block, or `None` for the text before the first header of the document
+    pub fn header(&self) -> Option<&FormattedLine> {
+        self.has_header().then_some(&self.header)
+    }
+
+    /// The lines below the header
+    pub fn contents(&self) -> &[FormattedLine] {
+        &self.contents
+    }
+
+    /// The lines below the header, for passes that change them
+    pub fn contents_mut(&mut self) -> &mut Vec<FormattedLine> {
+        &mut self.contents
+    }
+
     /// Returns the indentation level of the text contents the `Block` and sibling headers
     fn contents_indent_level(&self) -> usize {
         if self.is_before_first_header {
@@ -95,7 +168,7 @@ impl Block {
         }
     }
 
-    fn has_header(&self) -> bool {
+    pub fn has_header(&self) -> bool {
         !self.header.is_empty()
     }
 
@@ -122,8 +195,12 @@ impl Block {
     }
 }
 
+/// A line as it was in the parsed text
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RawLine {
+    /// The (1-based) line number in the parsed input, or `None` for lines that were added later
+    line_number: Option<usize>,
     num_indent: usize,
     raw: String,
     trimmed: String,
@@ -131,42 +208,100 @@ pub struct RawLine {
 
 impl RawLine {
     pub fn from_string(raw: &str) -> Self {
+        Self::from_string_with_tab_width(raw, consts::TAB_WIDTH)
+    }
+
+    /// Like `from_string()`, but a tab in the indenting of the line moves the indenting to the
+    /// next multiple of `tab_width` instead of the default.
+    pub fn from_string_with_tab_width(raw: &str, tab_width: usize) -> Self {
         let trimmed = raw.trim_start();
-        let num_indent = raw.len() - trimmed.len();
+        let num_indent = indent_width(&raw[..raw.len() - trimmed.len()], tab_width);
         let trimmed = trimmed.trim_end();
 
         RawLine {
+            line_number: None,
             num_indent,
             raw: raw.to_owned(),
             trimmed: trimmed.to_owned(),
         }
     }
 
-    fn is_empty(&self) -> bool {
+    /// The (1-based) line number in the parsed text, or `None` for lines that were added while
+    /// formatting
+    pub fn line_number(&self) -> Option<usize> {
+        self.line_number
+    }
+
+    /// The width of the indenting of the line, with tabs expanded
+    pub fn num_indent(&self) -> usize {
+        self.num_indent
+    }
+
+    /// The line without its line ending
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// The line without its indenting and trailing whitespace
+    pub fn trimmed(&self) -> &str {
+        &self.trimmed
+    }
+
+    /// Returns whether the line is empty or only contains whitespace
+    pub fn is_empty(&self) -> bool {
         self.raw.trim().is_empty()
     }
 
-    fn is_list_item(&self) -> bool {
+    fn is_list_item(&self, syntax: &Syntax) -> bool {
         matches!(
-            LineType::from_raw(&self.trimmed),
+            LineType::from_raw_with_syntax(&self.trimmed, syntax),
             LineType::ListBulletPoint | LineType::ListTodoItem
         )
     }
 
-    fn is_header(&self) -> bool {
-        LineType::from_raw(&self.trimmed) == LineType::Header
+    fn is_header(&self, syntax: &Syntax) -> bool {
+        LineType::from_raw_with_syntax(&self.trimmed, syntax) == LineType::Header
     }
+}
 
-    fn contains_marker(&self) -> bool {
-        self.trimmed
-            .starts_with(consts::MARKER_FENCED_FILETYPE_BACKTICK)
-            || self
-                .trimmed
-                .starts_with(consts::MARKER_FENCED_FILETYPE_TILDE)
+/// Returns the number of columns the whitespace takes up when each tab moves to the next multiple
+/// of `tab_width`.
+fn indent_width(whitespace: &str, tab_width: usize) -> usize {
+    whitespace.chars().fold(0, |width, c| match c {
+        '\t' if tab_width > 0 => (width / tab_width + 1) * tab_width,
+        _ => width + 1,
+    })
+}
+
+/// Removes the first `num_columns` columns of the indenting of `raw` and returns what is left of
+/// it, with any tabs kept as they are. A tab that is only partially removed is replaced by the
+/// spaces that remain of it.
+fn strip_indent_columns(raw: &str, num_columns: usize, tab_width: usize) -> String {
+    let indent = &raw[..raw.len() - raw.trim_start().len()];
+
+    let mut width = 0;
+    for (position, c) in indent.char_indices() {
+        if width >= num_columns {
+            return indent[position..].to_owned();
+        }
+
+        let next_width = indent_width(&indent[..position + c.len_utf8()], tab_width);
+        if next_width > num_columns {
+            return format!(
+                "{}{}",
+                " ".repeat(next_width - num_columns),
+                &indent[position + c.len_utf8()..]
+            );
+        }
+        width = next_width;
     }
+
+    String::new()
 }
 
+/// A line of a document with the type and indent level it was given while parsing
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FormattedLine {
     contents: String,
     indent_level: usize,
@@ -180,31 +315,84 @@ impl FormattedLine {
     }
 
     pub fn from_raw(raw_line: RawLine, indent_level: usize) -> Self {
+        Self::from_raw_with_syntax(raw_line, indent_level, &Syntax::default())
+    }
+
+    /// Like `from_raw()`, but detects the type of line using the prefixes of `syntax`. A line
+    /// that starts with an alternative prefix gets the canonical prefix instead.
+    pub fn from_raw_with_syntax(raw_line: RawLine, indent_level: usize, syntax: &Syntax) -> Self {
+        let (line_type, prefix_length) = syntax.detect(&raw_line.trimmed);
+        let canonical_prefix = syntax.prefix_of(line_type);
+
+        let contents = if prefix_length == 0 || raw_line.trimmed.starts_with(canonical_prefix) {
+            raw_line.trimmed.clone()
+        } else {
+            format!("{}{}", canonical_prefix, &raw_line.trimmed[prefix_length..])
+                .trim_end()
+                .to_owned()
+        };
+
         FormattedLine {
-            contents: raw_line.trimmed.clone(),
+            contents,
             indent_level,
-            line_type: LineType::from_raw(&raw_line.trimmed),
+            line_type,
             original_raw: raw_line,
         }
     }
 
-    fn is_empty(&self) -> bool {
+    /// The text of the line without its indenting, but including its prefix (e.g. `* `)
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// The level of indenting of the line, where every level is `INDENT_SHIFT` spaces or a tab
+    pub fn indent_level(&self) -> usize {
+        self.indent_level
+    }
+
+    pub fn line_type(&self) -> LineType {
+        self.line_type
+    }
+
+    /// Replaces the text of the line. The type of the line stays the same, so the new contents
+    /// should start with the same prefix.
+    pub fn set_contents(&mut self, contents: impl Into<String>) {
+        self.contents = contents.into();
+    }
+
+    /// The line this line was parsed from. Lines that were added while formatting, like wrapped
+    /// lines, have the `RawLine` of the line they were added for or an empty one.
+    pub fn original_raw(&self) -> &RawLine {
+        &self.original_raw
+    }
+
+    pub fn is_empty(&self) -> bool {
         self.contents.len() == 0
     }
 
-    fn is_list_item(&self) -> bool {
+    /// Returns whether the line is empty or only contains whitespace
+    pub fn is_blank(&self) -> bool {
+        self.contents.trim().is_empty()
+    }
+
+    /// Returns whether the line is a TODO item that is checked (`[x]`)
+    pub fn is_checked_todo_item(&self) -> bool {
+        self.line_type == LineType::ListTodoItem
+            && (self.contents.starts_with("[x]") || self.contents.starts_with("[X]"))
+    }
+
+    /// Returns whether the line is a bullet point, TODO item or the continuation of one
+    pub fn is_list_item(&self) -> bool {
         matches!(
             self.line_type,
             LineType::ListBulletPoint | LineType::ListTodoItem | LineType::ListContinuousLine
         )
     }
-
-    fn num_indent(&self) -> usize {
-        self.indent_level * consts::INDENT_SHIFT
-    }
 }
 
 #[derive(Debug, Eq, PartialEq, Default, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum LineType {
     #[default]
     Text,
@@ -218,6 +406,11 @@ pub enum LineType {
     /// A line that starts with a '|' is considered to be preformatted, and *can* be longer than
     /// the maximum line length.
     Preformatted,
+    /// A line within a fenced filetype block (e.g. code). Like preformatted lines these are never
+    /// wrapped.
+    FencedFiletype,
+    /// The line with the opening or closing marker of a fenced filetype block, e.g. "```rust"
+    FencedFiletypeMarker,
     /// A line that is prefixed with a '>'
     Quote,
 }
@@ -226,42 +419,34 @@ impl LineType {
     /// Detects the type of the given `line` by looking at its first characters.
     /// Note that this function cannot determine if the line is a 'continuation line' in a bullet
     /// point list since that requires knowledge about the line preceding this one.
-    fn from_raw(line: &str) -> Self {
-        if line.starts_with(consts::PREFIX_HEADER) {
-            LineType::Header
-        } else if line.starts_with(consts::PREFIX_BULLET_POINT) {
-            LineType::ListBulletPoint
-        } else if line.starts_with(consts::PREFIX_TODO_ITEM) {
-            LineType::ListTodoItem
-        } else if line.starts_with(consts::MARKER_FENCED_FILETYPE_BACKTICK)
-            || line.starts_with(consts::MARKER_FENCED_FILETYPE_TILDE)
-            || line.starts_with(consts::PREFIX_PREFORMATTED)
-        {
-            LineType::Preformatted
-        } else if line.starts_with(consts::PREFIX_QUOTE) {
-            LineType::Quote
-        } else {
-            LineType::Text
-        }
+    pub fn from_raw(line: &str) -> Self {
+        Self::from_raw_with_syntax(line, &Syntax::default())
     }
 
-    fn get_prefix(&self) -> &str {
-        match self {
-            Self::Header => consts::PREFIX_HEADER,
-            Self::ListBulletPoint => consts::PREFIX_BULLET_POINT,
-            Self::ListContinuousLine => consts::PREFIX_LIST_CONTINUATION,
-            Self::ListTodoItem => consts::PREFIX_TODO_ITEM,
-            Self::Preformatted => consts::PREFIX_PREFORMATTED,
-            Self::Quote => consts::PREFIX_QUOTE,
-            _ => "",
-        }
+    /// Like `from_raw()`, but detects the type of line using the prefixes of `syntax`
+    pub fn from_raw_with_syntax(line: &str, syntax: &Syntax) -> Self {
+        syntax.detect(line).0
+    }
+
+    /// Returns whether lines of this type must be kept as-is, i.e. not be wrapped or have their
+    /// whitespace changed.
+    pub fn is_preformatted(&self) -> bool {
+        matches!(
+            self,
+            Self::Preformatted | Self::FencedFiletype | Self::FencedFiletypeMarker
+        )
+    }
+
+    /// Returns the prefix that lines of this type start with, e.g. `* ` for bullet points
+    pub fn get_prefix<'a>(&self, syntax: &'a Syntax) -> &'a str {
+        syntax.prefix_of(*self)
     }
 
-    fn get_prefix_length(&self) -> usize {
+    pub fn get_prefix_length(&self, syntax: &Syntax) -> usize {
         match self {
             // '[ ] ' or '[x] '
             Self::ListTodoItem => 4,
-            _ => self.get_prefix().len(),
+            _ => self.get_prefix(syntax).len(),
         }
     }
 }
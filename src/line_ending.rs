@@ -0,0 +1,106 @@
+/// The characters that end a line
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineEnding {
+    /// `\n`, used on Linux, macOS, etc.
+    Lf,
+    /// `\r\n`, used on Windows
+    CrLf,
+}
+
+impl LineEnding {
+    /// The line ending that is common on the platform the formatter was built for
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    /// Returns the line ending that is used most in `contents`, or `None` if it doesn't contain
+    /// any line endings. When both are used equally often, the one of the first line wins.
+    ///
+    /// A lone `\r` (used by very old versions of Mac OS) counts as a `\n`.
+    pub fn detect(contents: &str) -> Option<Self> {
+        let mut num_lf = 0;
+        let mut num_crlf = 0;
+        let mut first = None;
+
+        let mut chars = contents.chars().peekable();
+        while let Some(c) = chars.next() {
+            let line_ending = match c {
+                '\r' if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                    LineEnding::CrLf
+                }
+                '\r' | '\n' => LineEnding::Lf,
+                _ => continue,
+            };
+
+            match line_ending {
+                LineEnding::Lf => num_lf += 1,
+                LineEnding::CrLf => num_crlf += 1,
+            }
+            first.get_or_insert(line_ending);
+        }
+
+        match num_lf.cmp(&num_crlf) {
+            std::cmp::Ordering::Greater => Some(LineEnding::Lf),
+            std::cmp::Ordering::Less => Some(LineEnding::CrLf),
+            std::cmp::Ordering::Equal => first,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+/// Splits `contents` into lines like `str::lines()`, but also treats a lone `\r` as the end of a
+/// line so that it can't end up in the text of a line.
+pub fn split_lines(contents: &str) -> impl Iterator<Item = &str> {
+    split_lines_with_endings(contents).map(|(line, _)| line)
+}
+
+/// Like `split_lines()`, but also returns the line ending of every line, which is empty for a last
+/// line that doesn't have one.
+pub fn split_lines_with_endings(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    let mut remaining = contents;
+
+    std::iter::from_fn(move || {
+        if remaining.is_empty() {
+            return None;
+        }
+
+        match remaining.find(['\r', '\n']) {
+            Some(position) => {
+                let line_ending_length = if remaining[position..].starts_with("\r\n") {
+                    2
+                } else {
+                    1
+                };
+                let (line, rest) = remaining.split_at(position);
+                let (line_ending, rest) = rest.split_at(line_ending_length);
+                remaining = rest;
+
+                Some((line, line_ending))
+            }
+            None => {
+                let line = remaining;
+                remaining = "";
+
+                Some((line, ""))
+            }
+        }
+    })
+}
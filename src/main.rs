@@ -1,13 +1,54 @@
+use std::collections::HashSet;
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::Path;
 
-use outlaw_format::format;
+use outlaw_format::{
+    consts, format_with_config, from_markdown_with_config, from_opml_with_config,
+    parse_document_with_config, to_html_with_config, to_markdown_with_config, to_opml_with_config,
+    Config, DeepHeaderStyle, Diagnostic, HtmlOptions, HtmlStylesheet, IndentStyle, LineEnding,
+    MarkdownOptions, Pipeline, Prefix, PreformattedStyle, PreformattedWhitespace, Severity,
+    UnclosedFencePolicy,
+};
+
+/// What is written to stdout
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Emit {
+    Formatted,
+    /// The parsed document as JSON, for debugging and for other tools
+    AstJson,
+    /// The formatted document converted to Markdown, with `export --to=markdown`
+    Markdown,
+    /// The formatted document converted to an HTML page, with `export --to=html`
+    Html,
+    /// The formatted document converted to OPML, with `export --to=opml`
+    Opml,
+}
+
+/// The format of the text that is read
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Input {
+    Outlaw,
+    /// A Markdown document that is converted to an outlaw document, with `import --from=markdown`
+    Markdown,
+    /// An OPML document that is converted to an outlaw document, with `import --from=opml`
+    Opml,
+}
+
+struct Args {
+    path: Option<String>,
+    config: Config,
+    input: Input,
+    emit: Emit,
+    markdown_options: MarkdownOptions,
+    html_options: HtmlOptions,
+}
 
 fn main() -> Result<(), String> {
-    let contents = if env::args().len() > 1 {
-        let path = env::args().nth(1).unwrap();
+    let args = parse_args(env::args().skip(1))?;
+
+    let contents = if let Some(path) = &args.path {
         let read_result = read_file(Path::new(&path));
 
         if let Err(err) = read_result {
@@ -29,11 +70,328 @@ fn main() -> Result<(), String> {
         }
     };
 
-    print!("{}", format(&contents));
+    match args.emit {
+        Emit::Formatted => {}
+        Emit::AstJson => return print_ast_json(&contents, &args.config),
+        Emit::Markdown | Emit::Html | Emit::Opml => return print_export(&contents, &args),
+    }
+
+    let formatted = match args.input {
+        Input::Outlaw => format_with_config(&contents, &args.config),
+        Input::Markdown => Pipeline::from_config(&args.config).format_document(
+            from_markdown_with_config(&contents, &args.config),
+            &args.config,
+        ),
+        Input::Opml => {
+            let document =
+                from_opml_with_config(&contents, &args.config).map_err(|diagnostic| {
+                    format!("The OPML document could not be read: {}", diagnostic)
+                })?;
+
+            Pipeline::from_config(&args.config).format_document(document, &args.config)
+        }
+    };
+    for diagnostic in formatted.diagnostics.iter() {
+        eprintln!("{}", diagnostic);
+    }
+
+    print!("{}", formatted.text);
+
+    if formatted
+        .diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return Err("The document was not formatted because of errors".to_owned());
+    }
 
     Ok(())
 }
 
+/// Parses the command line arguments. Options are given as `--name` or `--name=value`, any other
+/// argument is the path of the file to format. When the first argument is `export`, the document
+/// is converted to the format given with `--to` instead. When it is `import`, the file is
+/// converted from the format given with `--from` and then formatted.
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.peekable();
+    let mut path = None;
+    let mut config = Config::default();
+    let mut customized_markers = HashSet::new();
+    let mut input = Input::Outlaw;
+    let mut emit = Emit::Formatted;
+    let mut markdown_options = MarkdownOptions::default();
+    let mut html_options = HtmlOptions::default();
+    let is_export = args.next_if(|arg| arg == "export").is_some();
+    let is_import = !is_export && args.next_if(|arg| arg == "import").is_some();
+
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--") else {
+            path = Some(arg);
+            continue;
+        };
+
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        };
+
+        match name {
+            "completion-stamp" => {
+                let marker = value.unwrap_or(consts::MARKER_COMPLETION_STAMP);
+                if marker.trim().is_empty() {
+                    return Err("--completion-stamp expects a marker that isn't empty".to_owned());
+                }
+
+                config.completion_stamp = Some(marker.to_owned());
+            }
+            "sort-todo-items" => config.sort_todo_items = true,
+            "align-tables" => config.align_tables = true,
+            "number-headers" => config.number_headers = true,
+            "fence-formatter" => {
+                let Some((language, command)) = value.and_then(|value| value.split_once('='))
+                else {
+                    return Err(
+                        "--fence-formatter expects a value like LANGUAGE=COMMAND".to_owned()
+                    );
+                };
+
+                config
+                    .fence_formatters
+                    .insert(language.to_owned(), command.to_owned());
+            }
+            "unclosed-fence" => {
+                config.unclosed_fence_policy = match value {
+                    Some("warn") => UnclosedFencePolicy::Warn,
+                    Some("close") => UnclosedFencePolicy::CloseAtHeader,
+                    Some("refuse") => UnclosedFencePolicy::Refuse,
+                    _ => return Err("--unclosed-fence expects warn, close or refuse".to_owned()),
+                }
+            }
+            "known-languages" => {
+                config.known_fence_languages = value
+                    .unwrap_or("")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }
+            "normalize-languages" => config.normalize_fence_languages = true,
+            "language-alias" => {
+                let Some((alias, language)) = value.and_then(|value| value.split_once('=')) else {
+                    return Err("--language-alias expects a value like ALIAS=LANGUAGE".to_owned());
+                };
+
+                config
+                    .fence_language_aliases
+                    .insert(alias.to_lowercase(), language.to_owned());
+            }
+            "preformatted-style" => {
+                config.preformatted_style = match value {
+                    Some("keep") => PreformattedStyle::Keep,
+                    Some("fenced") => PreformattedStyle::Fenced,
+                    Some("prefixed") => PreformattedStyle::Prefixed,
+                    _ => {
+                        return Err(
+                            "--preformatted-style expects keep, fenced or prefixed".to_owned()
+                        )
+                    }
+                }
+            }
+            "header-marker" | "bullet-marker" | "preformatted-marker" | "quote-marker" => {
+                let Some(marker) = value.filter(|value| !value.trim().is_empty()) else {
+                    return Err(format!("--{} expects a marker", name));
+                };
+                // Markers are always followed by whitespace, which is easy to forget on the
+                // command line
+                let marker = if marker.ends_with(char::is_whitespace) {
+                    marker.to_owned()
+                } else {
+                    format!("{} ", marker)
+                };
+
+                let prefix = match name {
+                    "header-marker" => &mut config.syntax.header,
+                    "bullet-marker" => &mut config.syntax.bullet_point,
+                    "preformatted-marker" => &mut config.syntax.preformatted,
+                    _ => &mut config.syntax.quote,
+                };
+
+                // The first marker that is given replaces the default one, any following ones
+                // are alternatives
+                if customized_markers.insert(name.to_owned()) {
+                    *prefix = Prefix::new(&marker);
+                } else {
+                    prefix.alternatives.push(marker);
+                }
+            }
+            "continuation-indent" => {
+                let width = value
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|&width| width > 0)
+                    .ok_or("--continuation-indent expects a number larger than 0")?;
+                config.syntax.list_continuation = " ".repeat(width);
+            }
+            "tab-width" => {
+                config.tab_width = value
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&tab_width| tab_width > 0)
+                    .ok_or("--tab-width expects a number larger than 0")?
+            }
+            "keep-tabs" => config.preserve_preformatted_tabs = true,
+            "preformatted-whitespace" => {
+                config.preformatted_whitespace = match value {
+                    Some("preserve") => PreformattedWhitespace::Preserve,
+                    Some("strip") => PreformattedWhitespace::StripTrailing,
+                    Some("collapse") => PreformattedWhitespace::CollapseBlankLines,
+                    _ => {
+                        return Err(
+                            "--preformatted-whitespace expects preserve, strip or collapse"
+                                .to_owned(),
+                        )
+                    }
+                }
+            }
+            "indent-with-tabs" => config.indent_style = IndentStyle::Tabs,
+            "no-final-newline" => config.final_newline = false,
+            "blank-lines-before-headers" | "blank-lines-after-headers" => {
+                let blank_lines = value
+                    .unwrap_or("")
+                    .split(',')
+                    .map(|number| number.trim().parse())
+                    .collect::<Result<Vec<usize>, _>>()
+                    .map_err(|_| format!("--{} expects numbers like 2,1", name))?;
+
+                if name == "blank-lines-before-headers" {
+                    config.blank_lines_before_headers = blank_lines;
+                } else {
+                    config.blank_lines_after_headers = blank_lines;
+                }
+            }
+            "no-blank-line-after-empty-headers" => config.blank_line_after_empty_headers = false,
+            "no-blank-lines-between-list-items" => config.blank_lines_between_list_items = false,
+            "max-blank-lines" => {
+                config.max_consecutive_blank_lines = value
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--max-blank-lines expects a number")?
+            }
+            "newline" => {
+                config.line_ending = match value {
+                    Some("auto") => None,
+                    Some("lf") => Some(LineEnding::Lf),
+                    Some("crlf") => Some(LineEnding::CrLf),
+                    _ => return Err("--newline expects auto, lf or crlf".to_owned()),
+                }
+            }
+            "emit" => {
+                emit = match value.map(str::to_owned).or_else(|| args.next()).as_deref() {
+                    Some("formatted") => Emit::Formatted,
+                    Some("ast-json") => Emit::AstJson,
+                    _ => return Err("--emit expects formatted or ast-json".to_owned()),
+                }
+            }
+            "deep-headers" => {
+                markdown_options.deep_header_style = match value {
+                    Some("clamp") => DeepHeaderStyle::Clamp,
+                    Some("bold") => DeepHeaderStyle::Bold,
+                    _ => return Err("--deep-headers expects clamp or bold".to_owned()),
+                }
+            }
+            "stylesheet" => {
+                let Some(path) = value.map(str::to_owned).or_else(|| args.next()) else {
+                    return Err("--stylesheet expects the path of a CSS file".to_owned());
+                };
+                let stylesheet = read_file(Path::new(&path))
+                    .map_err(|err| format!("{}: Could not read stylesheet: {}", path, err))?;
+
+                html_options.stylesheet = HtmlStylesheet::Custom(stylesheet);
+            }
+            "no-stylesheet" => html_options.stylesheet = HtmlStylesheet::Omit,
+            // The format can also be given as a separate argument, like `--to markdown`
+            "to" if is_export => {
+                emit = match value.map(str::to_owned).or_else(|| args.next()).as_deref() {
+                    Some("markdown") => Emit::Markdown,
+                    Some("html") => Emit::Html,
+                    Some("opml") => Emit::Opml,
+                    _ => return Err("--to expects markdown, html or opml".to_owned()),
+                }
+            }
+            "from" if is_import => {
+                input = match value.map(str::to_owned).or_else(|| args.next()).as_deref() {
+                    Some("markdown") => Input::Markdown,
+                    Some("opml") => Input::Opml,
+                    _ => return Err("--from expects markdown or opml".to_owned()),
+                }
+            }
+            _ => return Err(format!("Unknown option: --{}", name)),
+        }
+    }
+
+    if is_export && !matches!(emit, Emit::Markdown | Emit::Html | Emit::Opml) {
+        return Err("export expects the format to export to, like --to=markdown".to_owned());
+    }
+    if is_import && (input == Input::Outlaw || emit != Emit::Formatted) {
+        return Err("import expects the format to import from, like --from=markdown".to_owned());
+    }
+
+    Ok(Args {
+        path,
+        config,
+        input,
+        emit,
+        markdown_options,
+        html_options,
+    })
+}
+
+/// Formats `contents` and prints it in the format of `args.emit`
+fn print_export(contents: &str, args: &Args) -> Result<(), String> {
+    let config = &args.config;
+    let mut document = parse_document_with_config(contents, config);
+    if !has_errors(&document.diagnostics) {
+        Pipeline::from_config(config).run(&mut document, config);
+    }
+
+    for diagnostic in document.diagnostics.iter() {
+        eprintln!("{}", diagnostic);
+    }
+    if has_errors(&document.diagnostics) {
+        return Err("The document was not exported because of errors".to_owned());
+    }
+
+    let exported = match args.emit {
+        Emit::Markdown => to_markdown_with_config(&document, config, &args.markdown_options),
+        Emit::Html => to_html_with_config(&document, config, &args.html_options),
+        Emit::Opml => to_opml_with_config(&document, config),
+        Emit::Formatted | Emit::AstJson => unreachable!("{:?} is not an export format", args.emit),
+    };
+    print!("{}", exported);
+
+    Ok(())
+}
+
+fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+#[cfg(feature = "serde")]
+fn print_ast_json(contents: &str, config: &Config) -> Result<(), String> {
+    let document = outlaw_format::parse_document_with_config(contents, config);
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|err| format!("Could not write the document as JSON: {}", err))?;
+
+    println!("{}", json);
+
+    Ok(())
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_ast_json(_contents: &str, _config: &Config) -> Result<(), String> {
+    Err("--emit=ast-json requires outlaw-format to be built with the `serde` feature".to_owned())
+}
+
 fn read_file(path: &Path) -> io::Result<String> {
     let mut file = File::open(path)?;
     let mut contents = String::with_capacity(fs::metadata(path)?.len() as usize);
@@ -54,6 +412,15 @@ fn read_stdin() -> io::Result<String> {
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::sync::Arc;
+
+    use outlaw_format::clock::{Date, FixedClock};
+    use outlaw_format::cst::{parse_syntax_tree, Element, NodeKind, TokenKind};
+    use outlaw_format::{
+        format, format_to_string, from_markdown, from_opml, parse_document, to_html, to_markdown,
+        to_opml, walk, Document, EditError, FormattedLine, LineType, Outline, Pass, Pipeline, Tree,
+        Visitor, WrapLongLines,
+    };
 
     use super::*;
 
@@ -107,6 +474,729 @@ mod tests {
         assert_equal(&actual, &expected);
     }
 
+    #[test]
+    fn fences_are_only_closed_by_matching_markers() {
+        let expected = read_file(Path::new("tests/nested_fences.expected")).unwrap();
+        let actual = format_file(Path::new("tests/nested_fences.input"));
+
+        assert_equal(&actual, &expected);
+    }
+
+    #[test]
+    fn unclosed_fences_are_reported() {
+        let input = read_file(Path::new("tests/unclosed_fences.input")).unwrap();
+        let formatted = format_with_config(&input, &Config::default());
+
+        // Without closing the first block, the second marker is part of the first block
+        let line_numbers = formatted
+            .diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.severity, diagnostic.line_number))
+            .collect::<Vec<_>>();
+        assert_eq!(line_numbers, vec![(Severity::Warning, Some(5))]);
+    }
+
+    #[test]
+    fn unclosed_fences_are_closed_at_next_header() {
+        let config = Config {
+            unclosed_fence_policy: UnclosedFencePolicy::CloseAtHeader,
+            ..Config::default()
+        };
+        let input = read_file(Path::new("tests/unclosed_fences.input")).unwrap();
+        let expected = read_file(Path::new("tests/unclosed_fences.expected")).unwrap();
+        let first_format = format_with_config(&input, &config);
+        let second_format = format_with_config(&first_format.text, &config);
+
+        let line_numbers = first_format
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.line_number)
+            .collect::<Vec<_>>();
+        assert_eq!(line_numbers, vec![Some(5), Some(14)]);
+
+        assert_equal(&second_format.text, &expected);
+        assert!(second_format.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unclosed_fences_can_refuse_formatting() {
+        let config = Config {
+            unclosed_fence_policy: UnclosedFencePolicy::Refuse,
+            ..Config::default()
+        };
+        let input = read_file(Path::new("tests/unclosed_fences.input")).unwrap();
+        let formatted = format_with_config(&input, &config);
+
+        assert_equal(&formatted.text, &input);
+        assert_eq!(formatted.diagnostics.len(), 1);
+        assert_eq!(formatted.diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn normalizing_and_checking_fence_languages() {
+        let config = Config {
+            known_fence_languages: vec!["python".to_owned(), "rust".to_owned()],
+            normalize_fence_languages: true,
+            ..Config::default()
+        };
+        let formatted = format_with_config(
+            "```  Py\nprint()\n```\n~~~RS linenos\n~~~\n```brainfuck\n+.\n```\n",
+            &config,
+        );
+
+        assert_equal(
+            &formatted.text,
+            "```python\nprint()\n```\n~~~rust linenos\n~~~\n```brainfuck\n+.\n```\n",
+        );
+        assert_eq!(formatted.diagnostics.len(), 1);
+        assert_eq!(formatted.diagnostics[0].line_number, Some(6));
+    }
+
+    #[test]
+    fn converting_between_preformatted_lines_and_fences() {
+        let fenced = read_file(Path::new("tests/preformatted_style.fenced")).unwrap();
+        let prefixed = read_file(Path::new("tests/preformatted_style.prefixed")).unwrap();
+
+        let to_fenced = Config {
+            preformatted_style: PreformattedStyle::Fenced,
+            ..Config::default()
+        };
+        assert_equal(&format_with_config(&prefixed, &to_fenced).text, &fenced);
+
+        let to_prefixed = Config {
+            preformatted_style: PreformattedStyle::Prefixed,
+            ..Config::default()
+        };
+        assert_equal(&format_with_config(&fenced, &to_prefixed).text, &prefixed);
+    }
+
+    #[test]
+    fn indented_backticks_dont_close_converted_fences() {
+        let config = Config {
+            preformatted_style: PreformattedStyle::Fenced,
+            ..Config::default()
+        };
+        let input = "=== H\n    | code\n    |     ```\n    | more\n";
+        let first_format = format_with_config(input, &config);
+        let second_format = format_with_config(&first_format.text, &config);
+
+        assert_equal(
+            &first_format.text,
+            "=== H\n\n    ````\n    code\n        ```\n    more\n    ````\n",
+        );
+        assert_equal(&second_format.text, &first_format.text);
+        assert!(first_format.diagnostics.is_empty() && second_format.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn custom_markers_are_normalized() {
+        let mut config = Config::default();
+        config.syntax.header = Prefix {
+            canonical: "## ".to_owned(),
+            alternatives: vec!["=== ".to_owned()],
+        };
+        config.syntax.bullet_point = Prefix {
+            canonical: "- ".to_owned(),
+            alternatives: vec!["* ".to_owned()],
+        };
+        config.syntax.quote.alternatives.push("» ".to_owned());
+
+        let expected = read_file(Path::new("tests/custom_markers.expected")).unwrap();
+        let first_format =
+            format_file_with_config(Path::new("tests/custom_markers.input"), &config);
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(&second_format, &expected);
+    }
+
+    #[test]
+    fn continuation_indent_matches_custom_bullet_marker() {
+        let args = ["--bullet-marker=->", "--continuation-indent=3"]
+            .into_iter()
+            .map(str::to_owned);
+        let config = Config {
+            max_line_length: 20,
+            ..parse_args(args).unwrap().config
+        };
+        let actual = format_with_config("-> A bullet point that is wrapped\n", &config).text;
+
+        assert_equal(&actual, "-> A bullet point\n   that is wrapped\n");
+    }
+
+    #[test]
+    fn tabs_are_expanded_when_determining_indenting() {
+        let config = Config {
+            tab_width: 4,
+            ..Config::default()
+        };
+        let expected = read_file(Path::new("tests/tabs.expected")).unwrap();
+        let actual = format_file_with_config(Path::new("tests/tabs.input"), &config);
+
+        assert_equal(&actual, &expected);
+    }
+
+    #[test]
+    fn tabs_in_fenced_blocks_can_be_kept() {
+        let config = Config {
+            tab_width: 4,
+            preserve_preformatted_tabs: true,
+            ..Config::default()
+        };
+        let actual = format_with_config(
+            "=== Code\n\t```go\n\tfunc main() {\n\t\treturn\n\t}\n\t```\n",
+            &config,
+        )
+        .text;
+
+        assert_equal(
+            &actual,
+            "=== Code\n\n    ```go\n    func main() {\n    \treturn\n    }\n    ```\n",
+        );
+    }
+
+    #[test]
+    fn fenced_blocks_round_trip_byte_for_byte() {
+        let input = read_file(Path::new("tests/preformatted_whitespace.input")).unwrap();
+        let input_with_crlf = input.replace('\n', "\r\n");
+
+        assert_equal(&format(&input), &input);
+        assert_equal(&format(&input_with_crlf), &input_with_crlf);
+    }
+
+    #[test]
+    fn whitespace_only_lines_in_nested_fenced_blocks_are_kept() {
+        let config = Config {
+            indent_style: IndentStyle::Tabs,
+            ..Config::default()
+        };
+        let input = "=== A\n\n\t=== B\n\n\t\t```\n\t\tx\n   \n\t\ty\n\t\t```\n";
+
+        assert_equal(&format_with_config(input, &config).text, input);
+    }
+
+    #[test]
+    fn fenced_blocks_keep_lines_that_look_like_headers_or_list_items() {
+        let input = read_file(Path::new("tests/fenced_look_alikes.input")).unwrap();
+        let formatted = format_with_config(&input, &Config::default());
+
+        assert_equal(&formatted.text, &input);
+        assert!(formatted.diagnostics.is_empty());
+
+        // Every marker keeps its type, so that the blocks are paired correctly
+        let document = parse_document(&input);
+        let num_markers = document
+            .blocks
+            .iter()
+            .flat_map(|block| block.contents())
+            .filter(|line| line.line_type() == LineType::FencedFiletypeMarker)
+            .count();
+        assert_eq!(num_markers, 6);
+    }
+
+    #[test]
+    fn trailing_whitespace_in_fenced_blocks_can_be_stripped() {
+        let config = Config {
+            preformatted_whitespace: PreformattedWhitespace::StripTrailing,
+            ..Config::default()
+        };
+        let actual =
+            format_file_with_config(Path::new("tests/preformatted_whitespace.input"), &config);
+
+        assert_equal(
+            &actual,
+            "=== Code\n\n    ```python\n    def f():\n        x = 1\n\n\n\n        return x\n    ```\n\n    \
+             Text.\n\n    ```\n    | not a table |\n\n    ```\n",
+        );
+    }
+
+    #[test]
+    fn blank_lines_in_fenced_blocks_can_be_collapsed() {
+        let config = Config {
+            preformatted_whitespace: PreformattedWhitespace::CollapseBlankLines,
+            ..Config::default()
+        };
+        let actual =
+            format_file_with_config(Path::new("tests/preformatted_whitespace.input"), &config);
+
+        assert_equal(
+            &actual,
+            "=== Code\n\n    ```python\n    def f():\n        x = 1\n\n        return x\n    ```\n\n    \
+             Text.\n\n    ```\n    | not a table |\n\n    ```\n",
+        );
+    }
+
+    #[test]
+    fn indenting_with_tabs() {
+        let config = Config {
+            indent_style: IndentStyle::Tabs,
+            max_line_length: 40,
+            ..Config::default()
+        };
+        let expected = read_file(Path::new("tests/indent_with_tabs.expected")).unwrap();
+        let first_format =
+            format_file_with_config(Path::new("tests/indent_with_tabs.input"), &config);
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(&second_format, &expected);
+    }
+
+    #[test]
+    fn line_endings_of_input_are_kept() {
+        let input = read_file(Path::new("tests/simple.input")).unwrap();
+        let expected = read_file(Path::new("tests/simple.expected")).unwrap();
+
+        assert_equal(&format(&input), &expected);
+        assert_eq!(
+            format(&input.replace('\n', "\r\n")),
+            expected.replace('\n', "\r\n")
+        );
+    }
+
+    #[test]
+    fn line_endings_can_be_overridden() {
+        let input = read_file(Path::new("tests/simple.input")).unwrap();
+        let expected = read_file(Path::new("tests/simple.expected")).unwrap();
+
+        let to_crlf = Config {
+            line_ending: Some(LineEnding::CrLf),
+            ..Config::default()
+        };
+        assert_eq!(
+            format_with_config(&input, &to_crlf).text,
+            expected.replace('\n', "\r\n")
+        );
+
+        let to_lf = Config {
+            line_ending: Some(LineEnding::Lf),
+            ..Config::default()
+        };
+        assert_eq!(
+            format_with_config(&input.replace('\n', "\r\n"), &to_lf).text,
+            expected
+        );
+    }
+
+    #[test]
+    fn mixed_line_endings_use_the_most_common_one() {
+        let actual = format("=== A\r\n\r\nText\n=== B\r\n\rText with a stray\rcarriage return\r\n");
+
+        assert_eq!(
+            actual,
+            "=== A\r\n\r\n    Text\r\n\r\n=== B\r\n\r\n    Text with a stray\r\n    carriage return\r\n"
+        );
+    }
+
+    #[test]
+    fn document_ends_with_exactly_one_newline() {
+        assert_equal(&format("=== Header\n\n\n"), "=== Header\n");
+        assert_equal(&format("=== Header\n\nText"), "=== Header\n\n    Text\n");
+
+        let config = Config {
+            final_newline: false,
+            ..Config::default()
+        };
+        assert_equal(
+            &format_with_config("=== Header\n\nText\n\n", &config).text,
+            "=== Header\n\n    Text",
+        );
+    }
+
+    #[test]
+    fn byte_order_mark_is_kept() {
+        let actual = format("\u{feff}=== Header\nText\n");
+
+        assert_equal(&actual, "\u{feff}=== Header\n\n    Text\n");
+    }
+
+    #[test]
+    fn leading_blank_lines_are_removed_without_touching_fenced_blocks() {
+        let actual = format("\n\n```\n\n    indented\n```\n");
+
+        assert_equal(&actual, "```\n\n    indented\n```\n");
+    }
+
+    #[test]
+    fn blank_lines_can_be_configured() {
+        let config = Config {
+            blank_lines_before_headers: vec![2, 1],
+            blank_lines_after_headers: vec![1, 0],
+            blank_line_after_empty_headers: false,
+            blank_lines_between_list_items: false,
+            max_consecutive_blank_lines: 2,
+            ..Config::default()
+        };
+        let expected = read_file(Path::new("tests/blank_lines.expected")).unwrap();
+        let first_format = format_file_with_config(Path::new("tests/blank_lines.input"), &config);
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(&first_format, &expected);
+        assert_equal(&second_format, &expected);
+    }
+
+    #[test]
+    fn parsed_document_can_be_inspected() {
+        let document = parse_document("Intro\n=== Plans\n    [x] Ship\n\n\t| code\n");
+
+        let first_block = &document.blocks[0];
+        assert!(first_block.header().is_none());
+        assert_eq!(first_block.contents()[0].contents(), "Intro");
+
+        let block = &document.blocks[1];
+        assert_eq!(block.header().unwrap().contents(), "=== Plans");
+        let lines = block
+            .contents()
+            .iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| (line.line_type(), line.indent_level(), line.contents()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                (LineType::ListTodoItem, 1, "[x] Ship"),
+                (LineType::Preformatted, 1, "| code"),
+            ]
+        );
+        assert!(block.contents()[0].is_checked_todo_item());
+
+        let raw_line = block.contents()[2].original_raw();
+        assert_eq!(raw_line.line_number(), Some(5));
+        assert_eq!(raw_line.raw(), "\t| code");
+        assert_eq!(raw_line.num_indent(), 4);
+        assert_eq!(
+            LineType::from_raw(raw_line.trimmed()),
+            LineType::Preformatted
+        );
+    }
+
+    #[test]
+    fn walking_a_document() {
+        #[derive(Default)]
+        struct Statistics {
+            headers: Vec<String>,
+            num_list_items: usize,
+            num_list_lines: usize,
+            todo_items: Vec<bool>,
+            fenced_blocks: Vec<(String, usize)>,
+            num_quotes: usize,
+        }
+
+        impl Visitor for Statistics {
+            fn visit_header(&mut self, header: &FormattedLine) {
+                self.headers.push(header.contents().to_owned());
+            }
+
+            fn visit_list_item(&mut self, lines: &[FormattedLine]) {
+                self.num_list_items += 1;
+                self.num_list_lines += lines.len();
+            }
+
+            fn visit_todo(&mut self, _lines: &[FormattedLine], is_checked: bool) {
+                self.todo_items.push(is_checked);
+            }
+
+            fn visit_fenced_block(&mut self, lines: &[FormattedLine]) {
+                self.fenced_blocks
+                    .push((lines[0].contents().to_owned(), lines.len()));
+            }
+
+            fn visit_quote(&mut self, _line: &FormattedLine) {
+                self.num_quotes += 1;
+            }
+        }
+
+        let mut statistics = Statistics::default();
+        let document = parse_document(
+            "=== Tasks\n    * one\n      wrapped\n    * two\n    [x] done\n    [ ] open\n\n    \
+             > quoted\n=== Code\n    ```sh\n    ls\n\n    ```\n    ~~~\n    ~~~\n",
+        );
+        walk(&document, &mut statistics);
+
+        assert_eq!(statistics.headers, vec!["=== Tasks", "=== Code"]);
+        assert_eq!(statistics.num_list_items, 2);
+        assert_eq!(statistics.num_list_lines, 3);
+        assert_eq!(statistics.todo_items, vec![true, false]);
+        assert_eq!(
+            statistics.fenced_blocks,
+            vec![("```sh".to_owned(), 4), ("~~~".to_owned(), 2)]
+        );
+        assert_eq!(statistics.num_quotes, 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn document_can_be_serialized() {
+        let input = read_file(Path::new("tests/full_document.input")).unwrap();
+        let document = parse_document(&input);
+
+        let json = serde_json::to_value(&document).unwrap();
+        let header = &json["blocks"][1]["header"];
+        assert_eq!(header["contents"], "=== Header 1");
+        assert_eq!(header["indent_level"], 0);
+        assert_eq!(header["line_type"], "Header");
+        assert_eq!(header["original_raw"]["line_number"], 5);
+
+        let deserialized: Document = serde_json::from_value(json).unwrap();
+        assert_equal(
+            &format_to_string(&deserialized),
+            &format_to_string(&document),
+        );
+    }
+
+    #[test]
+    fn document_as_tree_of_sections() {
+        let input = read_file(Path::new("tests/full_document.input")).unwrap();
+        let tree = Tree::from_document(&parse_document(&input));
+
+        let root = tree.root();
+        assert_eq!(root.title(), None);
+        assert_eq!(root.body().len(), 4);
+
+        let sections = root
+            .pre_order()
+            .skip(1)
+            .take(7)
+            .map(|node| (node.depth(), node.title().unwrap().to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                (1, "Header 1".to_owned()),
+                (2, "Header 1.1".to_owned()),
+                (2, "Header 1.2".to_owned()),
+                (1, "Header 2".to_owned()),
+                (2, "Header 2.1".to_owned()),
+                (3, "Header 2.1.1".to_owned()),
+                (4, "Header 2.1.1.1".to_owned()),
+            ]
+        );
+
+        let header_2_1_1 = root.pre_order().nth(6).unwrap();
+        assert_eq!(header_2_1_1.path(), [1, 0, 0]);
+        assert_eq!(header_2_1_1.parent().unwrap().title(), Some("Header 2.1"));
+        assert_eq!(header_2_1_1.children().count(), 1);
+    }
+
+    #[test]
+    fn tree_formats_like_the_document() {
+        for path in [
+            "tests/full_document.input",
+            "tests/fenced_filetypes.input",
+            "tests/preformatted_whitespace.input",
+        ] {
+            let document = parse_document(&read_file(Path::new(path)).unwrap());
+            let tree = Tree::from_document(&document);
+
+            assert_equal(
+                &format_to_string(&tree.to_document()),
+                &format_to_string(&document),
+            );
+        }
+    }
+
+    #[test]
+    fn editing_a_tree() {
+        let input = "=== Notes\n\n    * first\n\n    === Actions\n\n=== Archive\n\n    === Old\n";
+        let mut tree = Tree::from_document(&parse_document(input));
+
+        tree.insert_section(&[0, 0], "Attendees").unwrap();
+        tree.append_list_item(&[0, 0], "Alice").unwrap();
+        tree.append_text(&[0], "Second paragraph.").unwrap();
+        tree.append_todo_item(&[0, 1], "Send notes", false).unwrap();
+        tree.append_todo_item(&[0, 1], "Book room", true).unwrap();
+        tree.demote_section(&[1]).unwrap();
+        tree.promote_section(&[0, 2, 0]).unwrap();
+        let deleted = tree.delete_section(&[0, 2]).unwrap();
+        tree.move_section(&[0, 1], &[0]).unwrap();
+
+        assert_eq!(deleted.title(), Some("Archive"));
+        let formatted = format_to_string(&tree.to_document());
+        assert_equal(
+            &formatted,
+            "=== Actions\n\n    [ ] Send notes\n    [x] Book room\n\n=== Notes\n\n    * first\n\n    \
+             Second paragraph.\n\n    === Attendees\n\n        * Alice\n\n    === Old\n",
+        );
+        assert_equal(&format(&formatted), &formatted);
+    }
+
+    #[test]
+    fn invalid_edits_leave_the_tree_unchanged() {
+        let mut tree = Tree::from_document(&parse_document("=== A\n    === B\n=== C\n"));
+        let original = tree.clone();
+
+        assert_eq!(
+            tree.insert_section(&[5], "X"),
+            Err(EditError::NoSuchSection(vec![5]))
+        );
+        assert_eq!(
+            tree.promote_section(&[1]),
+            Err(EditError::CannotPromote(vec![1]))
+        );
+        assert_eq!(
+            tree.demote_section(&[0, 0]),
+            Err(EditError::CannotDemote(vec![0, 0]))
+        );
+        assert_eq!(
+            tree.move_section(&[0], &[0, 0, 0]),
+            Err(EditError::NoSuchSection(vec![0, 0, 0]))
+        );
+        assert!(tree.delete_section(&[]).is_err());
+        assert_eq!(
+            tree.append_text(&[0], "Fine\n* not a list"),
+            Err(EditError::NotText("* not a list".to_owned()))
+        );
+        assert_eq!(tree, original);
+    }
+
+    #[test]
+    fn building_an_outline() {
+        let config = Config {
+            max_line_length: 40,
+            line_ending: Some(LineEnding::Lf),
+            ..Config::default()
+        };
+        let outline = Outline::new()
+            .text("Release checklist")
+            .section("Plans", |plans| {
+                plans
+                    .list_item("Write the changelog")
+                    .text("Everything below has to be done before the release is tagged.")
+                    .section("Checks", |checks| {
+                        checks.todo("Tests pass", true).todo("Docs build", false)
+                    })
+            })
+            .section("Notes", |notes| notes);
+
+        let rendered = outline.render_with_config(&config).unwrap();
+        assert_equal(
+            &rendered,
+            "Release checklist\n\n=== Plans\n\n    * Write the changelog\n\n    Everything below has to be \
+             done before\n    the release is tagged.\n\n    === Checks\n\n        [x] Tests pass\n        \
+             [ ] Docs build\n\n=== Notes\n",
+        );
+        assert_equal(&format_with_config(&rendered, &config).text, &rendered);
+    }
+
+    #[test]
+    fn outline_text_round_trips() {
+        let outline = Outline::new().section("Notes", |notes| {
+            notes.text("> A quote\n| preformatted\n2 * 3 = 6\n#hashtag")
+        });
+        let rendered = outline.render().unwrap();
+        let tree = Tree::from_document(&parse_document(&rendered));
+
+        let line_types = |tree: &Tree| {
+            tree.root.subsections()[0]
+                .body()
+                .iter()
+                .filter(|line| !line.contents().is_empty())
+                .map(FormattedLine::line_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(line_types(&tree), line_types(&outline.into_tree().unwrap()));
+        assert_equal(&format_to_string(&tree.to_document()), &rendered);
+    }
+
+    #[test]
+    fn outline_text_that_looks_like_a_header_is_an_error() {
+        let not_text = EditError::NotText("=== Not a header".to_owned());
+        assert_eq!(
+            Outline::new().try_text("=== Not a header").unwrap_err(),
+            not_text
+        );
+
+        let outline = Outline::new()
+            .section("Notes", |notes| notes.text("Fine\n=== Not a header"))
+            .text("* not a list");
+        assert_eq!(outline.render(), Err(not_text));
+    }
+
+    #[test]
+    fn outline_titles_and_list_items_are_single_lines() {
+        let rendered = Outline::new()
+            .section("A\n=== B", |a| {
+                a.list_item("one\n=== two").todo("three\n* four", false)
+            })
+            .render()
+            .unwrap();
+
+        assert_equal(
+            &rendered,
+            "=== A === B\n\n    * one === two\n    [ ] three * four\n",
+        );
+        assert_eq!(
+            Tree::from_document(&parse_document(&rendered))
+                .root
+                .subsections()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn syntax_tree_reproduces_the_input() {
+        let mut inputs = fs::read_dir("tests")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.extension()
+                    .map_or(false, |extension| extension == "input")
+            })
+            .map(|path| read_file(&path).unwrap())
+            .collect::<Vec<_>>();
+        inputs.push("\u{feff}=== A\r\n\ttext  \r\n\r\n  === B\rno newline".to_owned());
+
+        for input in inputs {
+            let tree = parse_syntax_tree(&input);
+            let tokens = tree.root().tokens();
+
+            assert_equal(&tree.to_string(), &input);
+            // Every byte is part of exactly one token
+            assert!(tokens
+                .windows(2)
+                .all(|pair| pair[0].span.end == pair[1].span.start));
+        }
+    }
+
+    #[test]
+    fn syntax_tree_has_spans_of_sections_and_lines() {
+        let input = "Intro\n=== A\n    Text  \n    === B\n        * item\n=== C\n";
+        let tree = parse_syntax_tree(input);
+
+        let sections = tree
+            .root()
+            .children()
+            .iter()
+            .filter_map(|child| match child {
+                Element::Node(node) => Some(node),
+                _ => None,
+            })
+            .filter(|node| node.kind() == NodeKind::Section)
+            .map(|node| (node.span().first_line, node.span().last_line))
+            .collect::<Vec<_>>();
+        assert_eq!(sections, vec![(2, 5), (6, 6)]);
+
+        let item_offset = input.find("item").unwrap();
+        let line = tree.root().node_at(item_offset).unwrap();
+        assert_eq!(line.kind(), NodeKind::Line(LineType::ListBulletPoint));
+        assert_eq!(tree.text(line.span()), "        * item\n");
+
+        let text_line = tree.root().node_at(input.find("Text").unwrap()).unwrap();
+        let kinds = text_line
+            .tokens()
+            .iter()
+            .map(|token| (token.kind, tree.text(token.span)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::Indent, "    "),
+                (TokenKind::Text, "Text"),
+                (TokenKind::TrailingWhitespace, "  "),
+                (TokenKind::LineEnding, "\n"),
+            ]
+        );
+    }
+
     #[test]
     fn format_list_with_todo_items() {
         let expected = read_file(Path::new("tests/todo_items.expected")).unwrap();
@@ -125,6 +1215,394 @@ mod tests {
         assert_equal(&second_format, &expected);
     }
 
+    #[test]
+    fn stamping_completed_todo_items() {
+        let config = Config {
+            completion_stamp: Some(consts::MARKER_COMPLETION_STAMP.to_owned()),
+            clock: Arc::new(FixedClock(Date::new(2026, 10, 17))),
+            ..Config::default()
+        };
+        let expected = read_file(Path::new("tests/completion_stamps.expected")).unwrap();
+        let first_format =
+            format_file_with_config(Path::new("tests/completion_stamps.input"), &config);
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(&second_format, &expected);
+    }
+
+    #[test]
+    fn reopened_todo_item_loses_completion_stamp() {
+        let config = Config {
+            completion_stamp: Some(consts::MARKER_COMPLETION_STAMP.to_owned()),
+            clock: Arc::new(FixedClock(Date::new(2026, 10, 18))),
+            ..Config::default()
+        };
+        let actual = format_with_config(
+            "=== Header\n\n[ ] Item A ✓2026-10-17\n[x] Item B ✓2026-10-17\n",
+            &config,
+        )
+        .text;
+
+        assert_equal(
+            &actual,
+            "=== Header\n\n    [ ] Item A\n    [x] Item B ✓2026-10-17\n",
+        );
+    }
+
+    #[test]
+    fn empty_completion_stamp_marker_keeps_dates() {
+        let config = Config {
+            completion_stamp: Some(String::new()),
+            clock: Arc::new(FixedClock(Date::new(2026, 10, 18))),
+            ..Config::default()
+        };
+        let input = "=== Header\n\n    [ ] meet on 2026-10-17\n    [x] met on 2026-10-17\n";
+
+        assert_equal(&format_with_config(input, &config).text, input);
+
+        let args = ["--completion-stamp="].into_iter().map(str::to_owned);
+        assert!(parse_args(args).is_err());
+    }
+
+    #[test]
+    fn sorting_todo_items() {
+        let config = Config {
+            sort_todo_items: true,
+            max_line_length: 20,
+            ..Config::default()
+        };
+        let actual = format_with_config(
+            "=== Tasks\n    [x] Done first\n    [ ] Open with a long description\n    [x] Done \
+             second\n        * note\n    [ ] Open\n\n    * [x] Not a list of TODO items\n",
+            &config,
+        )
+        .text;
+
+        assert_equal(
+            &actual,
+            "=== Tasks\n\n    [ ] Open with a long\n      description\n    [ ] Open\n    [x] Done \
+             first\n    [x] Done second\n        * note\n\n    * [x] Not a list of\n      TODO \
+             items\n",
+        );
+    }
+
+    #[test]
+    fn numbering_headers() {
+        let config = Config {
+            number_headers: true,
+            ..Config::default()
+        };
+        let first_format = format_with_config(
+            "Intro\n=== 2. Plans\n    === 1.1. Now\n    === Later\n        === 2024 goals\n=== 1. \
+             Notes\n    === 1984. Orwell\n",
+            &config,
+        )
+        .text;
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(
+            &second_format,
+            "Intro\n\n=== 1. Plans\n\n    === 1.1. Now\n\n    === 1.2. Later\n\n        === \
+             1.2.1. 2024 goals\n\n=== 2. Notes\n\n    === 2.1. 1984. Orwell\n",
+        );
+    }
+
+    #[test]
+    fn renumbering_headers_after_deleting_one() {
+        let config = Config {
+            number_headers: true,
+            ..Config::default()
+        };
+        let numbered = format_with_config("=== A\n=== B\n=== C\n", &config).text;
+        assert_equal(&numbered, "=== 1. A\n\n=== 2. B\n\n=== 3. C\n");
+
+        let without_first = numbered.replacen("=== 1. A\n\n", "", 1);
+        assert_equal(
+            &format_with_config(&without_first, &config).text,
+            "=== 1. B\n\n=== 2. C\n",
+        );
+    }
+
+    #[test]
+    fn custom_passes_run_in_order() {
+        struct Shout;
+
+        impl Pass for Shout {
+            fn name(&self) -> &str {
+                "shout"
+            }
+
+            fn run(&self, document: &mut Document, _config: &Config) {
+                for block in document.blocks.iter_mut() {
+                    for line in block.contents_mut().iter_mut() {
+                        let contents = line.contents().to_uppercase();
+                        line.set_contents(contents);
+                    }
+                }
+            }
+        }
+
+        let config = Config {
+            max_line_length: 9,
+            ..Config::default()
+        };
+        let mut pipeline = Pipeline::from_config(&config);
+        pipeline.insert_before("wrap-long-lines", Shout);
+        assert_eq!(
+            pipeline.names(),
+            vec!["check-fence-languages", "shout", "wrap-long-lines"]
+        );
+
+        let formatted = pipeline.format("=== Header\n    quiet words here\n", &config);
+        assert_equal(
+            &formatted.text,
+            "=== Header\n\n    QUIET\n    WORDS\n    HERE\n",
+        );
+    }
+
+    #[test]
+    fn passes_can_run_on_their_own() {
+        let config = Config {
+            max_line_length: 9,
+            ..Config::default()
+        };
+        let mut document = parse_document("=== Header\n    quiet words here\n");
+        WrapLongLines.run(&mut document, &config);
+
+        assert_equal(
+            &format_to_string(&document),
+            "=== Header\n\n    quiet\n    words\n    here\n",
+        );
+    }
+
+    #[test]
+    fn aligning_tables() {
+        let config = Config {
+            align_tables: true,
+            ..Config::default()
+        };
+        let expected = read_file(Path::new("tests/tables.expected")).unwrap();
+        let first_format = format_file_with_config(Path::new("tests/tables.input"), &config);
+        let second_format = format_with_config(&first_format, &config).text;
+
+        assert_equal(&second_format, &expected);
+    }
+
+    #[test]
+    fn separators_without_rows_are_not_aligned() {
+        let config = Config {
+            align_tables: true,
+            ..Config::default()
+        };
+        let input = "=== Header\n\n    |---|\n    |-----------|\n";
+
+        assert_equal(&format_with_config(input, &config).text, input);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn formatting_fenced_blocks_with_external_command() {
+        let mut config = Config::default();
+        config
+            .fence_formatters
+            .insert("shout".to_owned(), "tr a-z A-Z".to_owned());
+        let expected = read_file(Path::new("tests/fence_formatters.expected")).unwrap();
+        let formatted = format_with_config(
+            &read_file(Path::new("tests/fence_formatters.input")).unwrap(),
+            &config,
+        );
+
+        assert_equal(&formatted.text, &expected);
+        assert!(formatted.diagnostics.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fence_formatter_gets_the_lines_of_its_own_block() {
+        let mut config = Config::default();
+        config
+            .fence_formatters
+            .insert("shout".to_owned(), "tr a-z A-Z".to_owned());
+        let input = "=== Commands\n\n    ```shout\n    * item\n    [x] done\n    ```\n\n    \
+                     Between the blocks.\n\n    ```shout\n    === second\n    ```\n";
+        let formatted = format_with_config(input, &config);
+
+        assert_equal(
+            &formatted.text,
+            "=== Commands\n\n    ```shout\n    * ITEM\n    [X] DONE\n    ```\n\n    \
+             Between the blocks.\n\n    ```shout\n    === SECOND\n    ```\n",
+        );
+        assert!(formatted.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn failing_fence_formatter_keeps_original_block() {
+        let mut config = Config::default();
+        config.fence_formatters.insert(
+            "shout".to_owned(),
+            "this-command-does-not-exist --flag".to_owned(),
+        );
+        let input = read_file(Path::new("tests/fence_formatters.input")).unwrap();
+        let formatted = format_with_config(&input, &config);
+
+        assert_equal(&formatted.text, &format(&input));
+        assert_eq!(formatted.diagnostics.len(), 1);
+        assert_eq!(formatted.diagnostics[0].line_number, Some(3));
+    }
+
+    #[test]
+    fn exporting_to_markdown() {
+        let document =
+            parse_document(&read_file(Path::new("tests/markdown_export.input")).unwrap());
+        let expected = read_file(Path::new("tests/markdown_export.md")).unwrap();
+
+        assert_equal(&to_markdown(&document), &expected);
+    }
+
+    #[test]
+    fn emit_option_takes_a_separate_value() {
+        for args in [
+            vec!["--emit", "ast-json", "notes.outlaw"],
+            vec!["--emit=ast-json", "notes.outlaw"],
+        ] {
+            let args = parse_args(args.into_iter().map(str::to_owned)).unwrap();
+
+            assert_eq!(args.emit, Emit::AstJson);
+            assert_eq!(args.path.as_deref(), Some("notes.outlaw"));
+        }
+    }
+
+    #[test]
+    fn exporting_deep_headers_to_markdown_as_bold_text() {
+        let args = ["export", "--to", "markdown", "--deep-headers=bold"]
+            .into_iter()
+            .map(str::to_owned);
+        let args = parse_args(args).unwrap();
+        let document = Outline::new()
+            .section("1", |s| {
+                s.section("2", |s| {
+                    s.section("3", |s| {
+                        s.section("4", |s| {
+                            s.section("5", |s| s.section("6", |s| s.section("7", |s| s)))
+                        })
+                    })
+                })
+            })
+            .to_document()
+            .unwrap();
+        let markdown = to_markdown_with_config(&document, &args.config, &args.markdown_options);
+
+        assert!(markdown.contains("\n###### 6\n\n**7**\n"));
+    }
+
+    #[test]
+    fn exporting_to_html() {
+        let options = HtmlOptions {
+            stylesheet: HtmlStylesheet::Omit,
+        };
+        let document = parse_document(&read_file(Path::new("tests/html_export.input")).unwrap());
+        let expected = read_file(Path::new("tests/html_export.html")).unwrap();
+
+        assert_equal(
+            &to_html_with_config(&document, &Config::default(), &options),
+            &expected,
+        );
+    }
+
+    #[test]
+    fn exported_html_embeds_stylesheet() {
+        let html = to_html(&parse_document("=== Title\n"));
+
+        assert!(html.contains(&format!("<style>\n{}</style>", consts::HTML_STYLESHEET)));
+    }
+
+    #[test]
+    fn stylesheet_option_takes_a_separate_value() {
+        let stylesheet = read_file(Path::new("tests/stylesheet.css")).unwrap();
+        for args in [
+            vec![
+                "export",
+                "--to=html",
+                "--stylesheet",
+                "tests/stylesheet.css",
+            ],
+            vec!["export", "--to=html", "--stylesheet=tests/stylesheet.css"],
+        ] {
+            let args = parse_args(args.into_iter().map(str::to_owned)).unwrap();
+
+            assert_eq!(
+                args.html_options.stylesheet,
+                HtmlStylesheet::Custom(stylesheet.clone())
+            );
+            assert_eq!(args.path, None);
+        }
+    }
+
+    #[test]
+    fn importing_markdown() {
+        let document = from_markdown(&read_file(Path::new("tests/markdown_import.md")).unwrap());
+        let expected = read_file(Path::new("tests/markdown_import.expected")).unwrap();
+        let formatted = Pipeline::from_config(&Config::default())
+            .format_document(document, &Config::default())
+            .text;
+
+        assert_equal(&formatted, &expected);
+    }
+
+    #[test]
+    fn markdown_round_trip() {
+        let expected = read_file(Path::new("tests/todo_items.expected")).unwrap();
+        let document = from_markdown(&to_markdown(&parse_document(&expected)));
+
+        assert_equal(&format_to_string(&document), &expected);
+    }
+
+    #[test]
+    fn exporting_to_opml() {
+        let contents = read_file(Path::new("tests/opml/outlaw.input")).unwrap();
+        let expected = read_file(Path::new("tests/opml/outlaw.opml")).unwrap();
+
+        assert_equal(&to_opml(&parse_document(&contents)), &expected);
+    }
+
+    #[test]
+    fn opml_round_trip() {
+        let expected = read_file(Path::new("tests/opml/outlaw.input")).unwrap();
+        let document = from_opml(&to_opml(&parse_document(&expected))).unwrap();
+
+        assert_equal(&format_to_string(&document), &expected);
+    }
+
+    #[test]
+    fn importing_opml() {
+        // OPML files of other outliners, which don't have the attributes that `to_opml()` adds
+        for name in ["workflowy", "omnioutliner", "entities"] {
+            let path = Path::new("tests/opml").join(name);
+            let document = from_opml(&read_file(&path.with_extension("opml")).unwrap()).unwrap();
+            let expected = read_file(&path.with_extension("expected")).unwrap();
+            let formatted = Pipeline::from_config(&Config::default())
+                .format_document(document, &Config::default())
+                .text;
+
+            assert_equal(&formatted, &expected);
+        }
+    }
+
+    #[test]
+    fn importing_malformed_opml() {
+        let error = from_opml(&read_file(Path::new("tests/opml/malformed.opml")).unwrap())
+            .expect_err("the OPML should be rejected");
+
+        assert_eq!(error.line_number, Some(6));
+        assert_eq!(
+            error.message,
+            "expected </outline> for the element on line 4, found </body>"
+        );
+
+        let error = from_opml("<html><body></body></html>").expect_err("the root isn't <opml>");
+        assert_eq!(error.message, "expected an <opml> element, found <html>");
+    }
+
     mod utils {
         use std::path::Path;
 
@@ -136,6 +1614,12 @@ mod tests {
             format(&contents)
         }
 
+        pub(super) fn format_file_with_config(path: &Path, config: &Config) -> String {
+            let contents = read_file(path).unwrap();
+
+            format_with_config(&contents, config).text
+        }
+
         pub(super) fn assert_equal(actual: &str, expected: &str) {
             if *actual != *expected {
                 let actual_lines = actual.lines().collect::<Vec<&str>>();
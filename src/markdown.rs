@@ -0,0 +1,825 @@
+use crate::line_ending::split_lines;
+use crate::tables::{is_table_row, is_table_separator};
+use crate::visitor::without_prefix;
+use crate::{
+    consts, strip_indent_columns, walk, Block, Config, Document, FormattedLine, LineEnding,
+    LineType, RawLine, Visitor,
+};
+
+/// Markdown only has six levels of headings
+const MAX_HEADING_LEVEL: usize = 6;
+
+/// How headers that are nested deeper than the six levels of Markdown headings are exported
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum DeepHeaderStyle {
+    /// Export them as `######` headings, like headers at the sixth level
+    #[default]
+    Clamp,
+    /// Export them as a paragraph with a bold title, like `**Title**`
+    Bold,
+}
+
+/// The options of `to_markdown_with_config()` that only matter for Markdown
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MarkdownOptions {
+    /// How headers below the sixth level are written
+    pub deep_header_style: DeepHeaderStyle,
+}
+
+/// The kind of Markdown block that was written last, which determines whether a blank line has to
+/// go before the next one
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum BlockKind {
+    Heading,
+    Paragraph,
+    List,
+    Quote,
+    CodeBlock,
+    Table,
+}
+
+/// Converts a parsed document to Markdown. Headers become headings (`#` for top level headers,
+/// `##` for the headers below them, etc.), bullet points and TODO items become (task) list items,
+/// and fenced filetype blocks are kept as they are. Runs of preformatted lines become a table
+/// when their second line is a separator (`|---|---|`), and a code block otherwise.
+///
+/// Text is written as is, so Markdown in it (like `*emphasis*`) keeps working. Only characters
+/// that would turn a line of text into something else, like a `#` at its start, are escaped.
+pub fn to_markdown(document: &Document) -> String {
+    to_markdown_with_config(document, &Config::default(), &MarkdownOptions::default())
+}
+
+/// Like `to_markdown()`, but uses the prefixes of `config.syntax`, writes headers nested deeper
+/// than six levels according to `options.deep_header_style` and ends lines like
+/// `format_to_string_with_config()` does.
+pub fn to_markdown_with_config(
+    document: &Document,
+    config: &Config,
+    options: &MarkdownOptions,
+) -> String {
+    let mut writer = MarkdownWriter {
+        config,
+        options,
+        linebreak: config.line_ending.unwrap_or(document.line_ending).as_str(),
+        markdown: String::new(),
+        last_block: None,
+        after_blank_line: false,
+        list_indent_levels: Vec::new(),
+        preformatted_lines: Vec::new(),
+    };
+
+    walk(document, &mut writer);
+    writer.finish()
+}
+
+struct MarkdownWriter<'a> {
+    config: &'a Config,
+    options: &'a MarkdownOptions,
+    linebreak: &'static str,
+    markdown: String,
+    last_block: Option<BlockKind>,
+    /// Whether a blank line was skipped since the last line that was written
+    after_blank_line: bool,
+    /// The indent levels of the list items that the last list item is nested in, followed by its
+    /// own indent level
+    list_indent_levels: Vec<usize>,
+    /// A run of preformatted lines, which is written once it is known whether it is a table
+    preformatted_lines: Vec<FormattedLine>,
+}
+
+impl MarkdownWriter<'_> {
+    fn finish(mut self) -> String {
+        self.write_preformatted_lines();
+
+        // Every line is written with a line ending, including the last one
+        if !self.config.final_newline {
+            let length = self.markdown.trim_end().len();
+            self.markdown.truncate(length);
+        }
+
+        self.markdown
+    }
+
+    /// Separates a block from the one before it with a blank line where Markdown needs one
+    fn start_block(&mut self, kind: BlockKind) {
+        if kind != BlockKind::List {
+            self.list_indent_levels.clear();
+        }
+
+        if let Some(last_block) = self.last_block {
+            let needs_blank_line = last_block != kind
+                || matches!(
+                    kind,
+                    BlockKind::Heading | BlockKind::CodeBlock | BlockKind::Table
+                )
+                // Blank lines between list items would make a 'loose' list
+                || (self.after_blank_line && kind != BlockKind::List);
+
+            if needs_blank_line {
+                self.markdown += self.linebreak;
+            }
+        }
+
+        self.last_block = Some(kind);
+        self.after_blank_line = false;
+    }
+
+    fn write_line(&mut self, line: &str) {
+        self.markdown += line;
+        self.markdown += self.linebreak;
+    }
+
+    fn write_list_item(&mut self, marker: &str, lines: &[FormattedLine]) {
+        self.write_preformatted_lines();
+        self.start_block(BlockKind::List);
+
+        // Items are nested by one level deeper than the item they follow at most, since Markdown
+        // would turn a deeper indented item into a code block
+        let indent_level = lines[0].indent_level();
+        while self
+            .list_indent_levels
+            .last()
+            .map_or(false, |&level| level > indent_level)
+        {
+            self.list_indent_levels.pop();
+        }
+        if self
+            .list_indent_levels
+            .last()
+            .map_or(true, |&level| level < indent_level)
+        {
+            self.list_indent_levels.push(indent_level);
+        }
+
+        // Wrapped lines are joined, Markdown wraps the item by itself
+        let text = std::iter::once(without_prefix(&lines[0], &self.config.syntax))
+            .chain(lines[1..].iter().map(|line| line.contents().trim()))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        // The text of an item can start a nested block as well, or a task of a bullet point
+        let mut text = escape_line_start(&text);
+        if lines[0].line_type() == LineType::ListBulletPoint && parse_task(&text).is_some() {
+            text.insert(0, '\\');
+        }
+
+        let line = format!(
+            "{}{}{}",
+            "  ".repeat(self.list_indent_levels.len() - 1),
+            marker,
+            text
+        );
+        self.write_line(line.trim_end());
+    }
+
+    fn write_preformatted_lines(&mut self) {
+        if self.preformatted_lines.is_empty() {
+            return;
+        }
+
+        let lines = std::mem::take(&mut self.preformatted_lines);
+        let is_table =
+            lines.len() >= 2 && lines.iter().all(is_table_row) && is_table_separator(&lines[1]);
+
+        if is_table {
+            self.start_block(BlockKind::Table);
+            for line in lines.iter() {
+                self.write_line(line.contents());
+            }
+        } else {
+            let contents: Vec<&str> = lines
+                .iter()
+                .map(|line| without_prefix(line, &self.config.syntax))
+                .collect();
+            let fence = code_fence(&contents);
+
+            self.start_block(BlockKind::CodeBlock);
+            self.write_line(&fence);
+            for line in contents {
+                self.write_line(line);
+            }
+            self.write_line(&fence);
+        }
+    }
+}
+
+impl Visitor for MarkdownWriter<'_> {
+    fn visit_header(&mut self, header: &FormattedLine) {
+        self.write_preformatted_lines();
+
+        let title = without_prefix(header, &self.config.syntax).trim();
+        let level = header.indent_level() + 1;
+        let line = match self.options.deep_header_style {
+            DeepHeaderStyle::Bold if level > MAX_HEADING_LEVEL => {
+                if title.is_empty() {
+                    // `****` would be read as a horizontal rule
+                    return;
+                }
+
+                format!("**{}**", title)
+            }
+            _ => format!("{} {}", "#".repeat(level.min(MAX_HEADING_LEVEL)), title),
+        };
+
+        self.start_block(BlockKind::Heading);
+        self.write_line(line.trim_end());
+    }
+
+    fn visit_text(&mut self, line: &FormattedLine) {
+        // A separator of a table that is written without spaces (`|---|`) isn't preformatted
+        if !self.preformatted_lines.is_empty() && is_table_separator(line) {
+            self.preformatted_lines.push(line.clone());
+            return;
+        }
+
+        self.write_preformatted_lines();
+        self.start_block(BlockKind::Paragraph);
+        self.write_line(&escape_line_start(line.contents().trim_start()));
+    }
+
+    fn visit_blank_line(&mut self, _line: &FormattedLine) {
+        self.write_preformatted_lines();
+        self.after_blank_line = true;
+    }
+
+    fn visit_list_item(&mut self, lines: &[FormattedLine]) {
+        self.write_list_item("- ", lines);
+    }
+
+    fn visit_todo(&mut self, lines: &[FormattedLine], is_checked: bool) {
+        self.write_list_item(if is_checked { "- [x] " } else { "- [ ] " }, lines);
+    }
+
+    fn visit_fenced_block(&mut self, lines: &[FormattedLine]) {
+        self.write_preformatted_lines();
+        self.start_block(BlockKind::CodeBlock);
+
+        // The block can be missing its opening marker when it continues after a header, and its
+        // closing marker when it was never closed
+        let has_opening_marker = lines[0].line_type() == LineType::FencedFiletypeMarker;
+        let opening_marker = if has_opening_marker {
+            lines[0].contents()
+        } else {
+            "```"
+        };
+        let has_closing_marker = lines.len() > usize::from(has_opening_marker)
+            && lines[lines.len() - 1].line_type() == LineType::FencedFiletypeMarker;
+        let closing_marker = if has_closing_marker {
+            lines[lines.len() - 1].contents()
+        } else {
+            opening_marker.trim_end_matches(|c| c != '`' && c != '~')
+        };
+
+        self.write_line(opening_marker);
+        for line in
+            &lines[usize::from(has_opening_marker)..lines.len() - usize::from(has_closing_marker)]
+        {
+            self.write_line(line.contents());
+        }
+        self.write_line(closing_marker);
+    }
+
+    fn visit_preformatted(&mut self, line: &FormattedLine) {
+        self.preformatted_lines.push(line.clone());
+    }
+
+    fn visit_quote(&mut self, line: &FormattedLine) {
+        self.write_preformatted_lines();
+        self.start_block(BlockKind::Quote);
+
+        let line = format!("> {}", without_prefix(line, &self.config.syntax));
+        self.write_line(line.trim_end());
+    }
+}
+
+/// Converts a Markdown document to an outlaw document, which can be formatted with
+/// `Pipeline::format_document()`.
+///
+/// Headings (`#` and underlined ones) become headers, nested by their level. Bullet points,
+/// numbered list items (which keep their number) and task list items (`- [ ]`) become bullet points
+/// and TODO items, nested by their indenting. Block quotes, code fences and tables are kept, and
+/// indented code blocks become preformatted lines. Horizontal rules are left out.
+pub fn from_markdown(markdown: &str) -> Document {
+    from_markdown_with_config(markdown, &Config::default())
+}
+
+/// Like `from_markdown()`, but uses the prefixes of `config.syntax` and its tab width for the
+/// indenting of the Markdown
+pub fn from_markdown_with_config(markdown: &str, config: &Config) -> Document {
+    let mut reader = MarkdownReader {
+        config,
+        document: Document::new(),
+        heading_levels: Vec::new(),
+        list_indents: Vec::new(),
+        paragraph: Vec::new(),
+        code_fence: None,
+        after_blank_line: false,
+    };
+
+    let markdown = match markdown.strip_prefix(consts::BYTE_ORDER_MARK) {
+        Some(markdown) => {
+            reader.document.has_byte_order_mark = true;
+            markdown
+        }
+        None => markdown,
+    };
+    if let Some(line_ending) = LineEnding::detect(markdown) {
+        reader.document.line_ending = line_ending;
+    }
+
+    for (index, line) in split_lines(markdown).enumerate() {
+        let mut raw_line = RawLine::from_string_with_tab_width(line, config.tab_width);
+        raw_line.line_number = Some(index + 1);
+
+        reader.read_line(raw_line);
+    }
+
+    reader.finish()
+}
+
+/// A code fence that was opened, like "```rust"
+struct CodeFence {
+    /// The characters of the fence, which close it when a line starts with at least as many of them
+    marker: String,
+    /// The indenting of the opening fence, which is removed from the lines of the block
+    num_indent: usize,
+}
+
+struct MarkdownReader<'a> {
+    config: &'a Config,
+    document: Document,
+    /// The levels of the headings of the sections that the current section is nested in, followed
+    /// by the level of its own heading
+    heading_levels: Vec<usize>,
+    /// The indenting of the list items that the last list item is nested in, followed by its own
+    /// indenting
+    list_indents: Vec<usize>,
+    /// The lines of a paragraph, which are added once it is known whether they are a heading
+    paragraph: Vec<RawLine>,
+    code_fence: Option<CodeFence>,
+    after_blank_line: bool,
+}
+
+impl MarkdownReader<'_> {
+    fn finish(mut self) -> Document {
+        self.add_paragraph();
+
+        // Markdown closes a code fence at the end of the document
+        if let Some(code_fence) = self.code_fence.take() {
+            self.add_line(LineType::FencedFiletypeMarker, code_fence.marker, None);
+        }
+
+        self.document
+    }
+
+    fn read_line(&mut self, raw_line: RawLine) {
+        if let Some(code_fence) = &self.code_fence {
+            if is_closing_fence(raw_line.trimmed(), &code_fence.marker) {
+                self.add_line(
+                    LineType::FencedFiletypeMarker,
+                    raw_line.trimmed().to_owned(),
+                    Some(raw_line),
+                );
+                self.code_fence = None;
+            } else {
+                let contents = format!(
+                    "{}{}",
+                    strip_indent_columns(
+                        raw_line.raw(),
+                        code_fence.num_indent,
+                        self.config.tab_width
+                    ),
+                    raw_line.raw().trim_start()
+                );
+                self.add_line(LineType::FencedFiletype, contents, Some(raw_line));
+            }
+
+            return;
+        }
+
+        if raw_line.is_empty() {
+            self.add_paragraph();
+            self.add_line(LineType::Text, String::new(), Some(raw_line));
+            self.after_blank_line = true;
+            return;
+        }
+
+        let trimmed = raw_line.trimmed();
+        let is_underline = !trimmed.is_empty()
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'));
+
+        if raw_line.num_indent() >= 4 && self.paragraph.is_empty() && self.list_indents.is_empty() {
+            // An indented code block
+            let contents = format!(
+                "{}{}",
+                strip_indent_columns(raw_line.raw(), 4, self.config.tab_width),
+                raw_line.trimmed()
+            );
+            self.add_preformatted_line(&contents, raw_line);
+        } else if let Some(marker) = opening_fence(trimmed) {
+            self.add_paragraph();
+            self.code_fence = Some(CodeFence {
+                marker: marker.to_owned(),
+                num_indent: raw_line.num_indent(),
+            });
+            self.add_line(
+                LineType::FencedFiletypeMarker,
+                trimmed.to_owned(),
+                Some(raw_line),
+            );
+        } else if let Some((level, title)) = parse_atx_heading(trimmed) {
+            self.add_paragraph();
+            let title = title.to_owned();
+            self.add_heading(level, &title, raw_line);
+        } else if is_underline && !self.paragraph.is_empty() {
+            // The paragraph turns out to be an underlined (Setext) heading
+            let level = if trimmed.starts_with('=') { 1 } else { 2 };
+            let paragraph = std::mem::take(&mut self.paragraph);
+            let title = paragraph
+                .iter()
+                .map(RawLine::trimmed)
+                .collect::<Vec<&str>>()
+                .join(" ");
+
+            let first_line = paragraph.into_iter().next().unwrap_or(raw_line);
+            self.add_heading(level, &title, first_line);
+        } else if is_horizontal_rule(trimmed) {
+            self.add_paragraph();
+            self.list_indents.clear();
+        } else if let Some((marker, text)) = parse_list_item(trimmed) {
+            self.add_paragraph();
+            let (marker, text) = (marker.to_owned(), text.to_owned());
+            self.add_list_item(&marker, &text, raw_line);
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            self.add_paragraph();
+            let quote = quote.strip_prefix(' ').unwrap_or(quote);
+            if quote.is_empty() {
+                self.add_line(LineType::Text, String::new(), Some(raw_line));
+            } else {
+                let contents = format!("{}{}", self.config.syntax.quote.canonical, quote);
+                self.add_line(LineType::Quote, contents, Some(raw_line));
+            }
+        } else if trimmed.starts_with('|') {
+            self.add_paragraph();
+            let contents = trimmed.to_owned();
+            self.add_preformatted_line(&contents, raw_line);
+        } else if self.paragraph.is_empty() && !self.after_blank_line && self.is_after_list_item() {
+            // A (lazy) continuation of the list item before it, which is joined with the item
+            let last_line = self
+                .document
+                .last_block_mut()
+                .contents
+                .last_mut()
+                .expect("there should be a list item");
+            last_line.contents = format!("{} {}", last_line.contents, trimmed);
+        } else {
+            self.paragraph.push(raw_line);
+        }
+    }
+
+    /// Adds a line below the last header, at the indent level of the contents of the header
+    fn add_line(&mut self, line_type: LineType, contents: String, raw_line: Option<RawLine>) {
+        if line_type != LineType::Text || !contents.is_empty() {
+            self.after_blank_line = false;
+        }
+        // A list goes on after blank lines and lines that are indented more than its items, like
+        // the paragraphs of a loose list
+        let is_in_list = matches!(
+            line_type,
+            LineType::ListBulletPoint | LineType::ListTodoItem
+        ) || (line_type == LineType::Text && contents.is_empty())
+            || raw_line.as_ref().map_or(false, |raw_line| {
+                self.list_indents
+                    .first()
+                    .map_or(false, |&indent| raw_line.num_indent() > indent)
+            });
+        if !is_in_list {
+            self.list_indents.clear();
+        }
+
+        let block = self.document.last_block_mut();
+        let indent_level = block.contents_indent_level();
+        block.add_line(FormattedLine {
+            contents,
+            indent_level,
+            line_type,
+            original_raw: raw_line.unwrap_or_default(),
+        });
+    }
+
+    fn add_paragraph(&mut self) {
+        let paragraph = std::mem::take(&mut self.paragraph);
+        if self.is_in_list_item(&paragraph) {
+            return self.add_list_item_paragraph(paragraph);
+        }
+
+        for raw_line in paragraph {
+            let contents = unescape_line_start(raw_line.trimmed());
+            self.add_line(LineType::Text, contents, Some(raw_line));
+        }
+    }
+
+    /// Returns whether a paragraph is indented more than the list before it, like the paragraph of
+    /// an item of a loose list
+    fn is_in_list_item(&self, paragraph: &[RawLine]) -> bool {
+        let (Some(first_line), Some(&list_indent)) = (paragraph.first(), self.list_indents.first())
+        else {
+            return false;
+        };
+
+        first_line.num_indent() > list_indent
+            && self
+                .document
+                .last_block()
+                .contents
+                .iter()
+                .rfind(|line| !line.is_blank())
+                .map_or(false, FormattedLine::is_list_item)
+    }
+
+    /// Adds the lines of a paragraph that belongs to the last list item as continuation lines of
+    /// the item. A list item can't contain blank lines, so the ones before the paragraph are
+    /// removed.
+    fn add_list_item_paragraph(&mut self, paragraph: Vec<RawLine>) {
+        let prefix = LineType::ListContinuousLine.get_prefix(&self.config.syntax);
+        let block = self.document.last_block_mut();
+        let num_lines = block
+            .contents
+            .iter()
+            .rposition(|line| !line.is_blank())
+            .map_or(0, |index| index + 1);
+        block.contents.truncate(num_lines);
+
+        let indent_level = block
+            .contents
+            .last()
+            .expect("there should be a list item")
+            .indent_level;
+        for raw_line in paragraph {
+            block.add_line(FormattedLine {
+                contents: format!("{}{}", prefix, unescape_line_start(raw_line.trimmed())),
+                indent_level,
+                line_type: LineType::ListContinuousLine,
+                original_raw: raw_line,
+            });
+        }
+        self.after_blank_line = false;
+    }
+
+    fn add_heading(&mut self, level: usize, title: &str, raw_line: RawLine) {
+        while self
+            .heading_levels
+            .last()
+            .map_or(false, |&heading_level| heading_level >= level)
+        {
+            self.heading_levels.pop();
+        }
+        self.heading_levels.push(level);
+
+        let header = FormattedLine {
+            contents: format!("{}{}", self.config.syntax.header.canonical, title),
+            indent_level: self.heading_levels.len() - 1,
+            line_type: LineType::Header,
+            original_raw: raw_line,
+        };
+        self.document.add_block(Block::new(header));
+        self.list_indents.clear();
+        self.after_blank_line = false;
+    }
+
+    fn add_list_item(&mut self, marker: &str, text: &str, raw_line: RawLine) {
+        let (line_type, contents) = match parse_task(text) {
+            Some((is_checked, text)) => (
+                LineType::ListTodoItem,
+                format!(
+                    "[{}] {}",
+                    if is_checked { 'x' } else { ' ' },
+                    unescape_line_start(text)
+                ),
+            ),
+            None => {
+                let text = unescape_line_start(text);
+
+                // Numbered list items keep their number, since there are no numbered lists
+                let number = if marker.starts_with(|c: char| c.is_ascii_digit()) {
+                    format!("{} ", marker)
+                } else {
+                    String::new()
+                };
+
+                (
+                    LineType::ListBulletPoint,
+                    format!(
+                        "{}{}{}",
+                        self.config.syntax.bullet_point.canonical, number, text
+                    ),
+                )
+            }
+        };
+
+        // Items are nested by their indenting, one level deeper than the item before them at most
+        let num_indent = raw_line.num_indent();
+        while self
+            .list_indents
+            .last()
+            .map_or(false, |&indent| indent > num_indent)
+        {
+            self.list_indents.pop();
+        }
+        if self
+            .list_indents
+            .last()
+            .map_or(true, |&indent| indent < num_indent)
+        {
+            self.list_indents.push(num_indent);
+        }
+
+        self.add_line(line_type, contents.trim_end().to_owned(), Some(raw_line));
+
+        let last_line = self
+            .document
+            .last_block_mut()
+            .contents
+            .last_mut()
+            .expect("the list item was just added");
+        last_line.indent_level += self.list_indents.len() - 1;
+    }
+
+    fn add_preformatted_line(&mut self, contents: &str, raw_line: RawLine) {
+        let prefix = &self.config.syntax.preformatted.canonical;
+        let line = FormattedLine {
+            contents: contents.to_owned(),
+            line_type: LineType::Text,
+            ..FormattedLine::default()
+        };
+
+        let (line_type, contents) = if contents.starts_with(prefix.as_str()) {
+            (LineType::Preformatted, contents.to_owned())
+        } else if is_table_separator(&line) {
+            // Like a separator that was written without spaces in an outlaw document
+            (LineType::Text, contents.to_owned())
+        } else {
+            (
+                LineType::Preformatted,
+                format!("{}{}", prefix, contents).trim_end().to_owned(),
+            )
+        };
+
+        self.add_line(line_type, contents, Some(raw_line));
+    }
+
+    fn is_after_list_item(&self) -> bool {
+        self.document
+            .last_block()
+            .contents
+            .last()
+            .map_or(false, |line| {
+                matches!(
+                    line.line_type,
+                    LineType::ListBulletPoint | LineType::ListTodoItem
+                )
+            })
+    }
+}
+
+/// Returns the fence (e.g. "```") a line starts with if it opens a code block
+fn opening_fence(trimmed: &str) -> Option<&str> {
+    let fence_char = trimmed.chars().next().filter(|&c| c == '`' || c == '~')?;
+    let fence = trimmed.trim_start_matches(fence_char);
+    let fence_length = trimmed.len() - fence.len();
+
+    // The info string of a backtick fence can't contain backticks
+    if fence_length < 3 || (fence_char == '`' && fence.contains('`')) {
+        return None;
+    }
+
+    Some(&trimmed[..fence_length])
+}
+
+fn is_closing_fence(trimmed: &str, marker: &str) -> bool {
+    let fence_char = marker.chars().next().unwrap_or('`');
+    let rest = trimmed.trim_start_matches(fence_char);
+
+    trimmed.len() - rest.len() >= marker.len() && rest.trim().is_empty()
+}
+
+/// Returns the level and title of a heading like `## Title ##`
+fn parse_atx_heading(trimmed: &str) -> Option<(usize, &str)> {
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    let rest = &trimmed[level..];
+    if !(1..=MAX_HEADING_LEVEL).contains(&level)
+        || !(rest.is_empty() || rest.starts_with([' ', '\t']))
+    {
+        return None;
+    }
+
+    // The title can be followed by a closing sequence of `#`s
+    let title = rest.trim();
+    let without_closing_sequence = title.trim_end_matches('#');
+    if without_closing_sequence.is_empty() || without_closing_sequence.ends_with([' ', '\t']) {
+        Some((level, without_closing_sequence.trim_end()))
+    } else {
+        Some((level, title))
+    }
+}
+
+/// Returns whether a line is a horizontal rule, like `---` or `* * *`
+fn is_horizontal_rule(trimmed: &str) -> bool {
+    let Some(rule_char) = trimmed
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '-' | '*' | '_'))
+    else {
+        return false;
+    };
+
+    trimmed.chars().filter(|&c| c == rule_char).count() >= 3
+        && trimmed
+            .chars()
+            .all(|c| c == rule_char || c == ' ' || c == '\t')
+}
+
+/// Returns the marker of a list item (e.g. `-` or `1.`) and the text after it
+fn parse_list_item(trimmed: &str) -> Option<(&str, &str)> {
+    let marker_length = if trimmed.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let num_digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+        if !(1..=9).contains(&num_digits) || !trimmed[num_digits..].starts_with(['.', ')']) {
+            return None;
+        }
+
+        num_digits + 1
+    };
+
+    let (marker, text) = trimmed.split_at(marker_length);
+    if text.is_empty() || text.starts_with([' ', '\t']) {
+        Some((marker, text.trim_start()))
+    } else {
+        None
+    }
+}
+
+/// Returns whether the text of a list item starts with a task list marker (`[ ]` or `[x]`), and the
+/// text after it
+fn parse_task(text: &str) -> Option<(bool, &str)> {
+    let is_checked = match text.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+
+    let rest = &text[3..];
+    if rest.is_empty() || rest.starts_with([' ', '\t']) {
+        Some((is_checked, rest.trim_start()))
+    } else {
+        None
+    }
+}
+
+/// Removes the escaping that `to_markdown()` adds to the start of a line of text
+fn unescape_line_start(text: &str) -> String {
+    let num_digits = text.chars().take_while(char::is_ascii_digit).count();
+    let (before, rest) = text.split_at(num_digits);
+
+    match rest.strip_prefix('\\') {
+        Some(escaped) if escaped.starts_with(|c: char| c.is_ascii_punctuation()) => {
+            format!("{}{}", before, escaped)
+        }
+        _ => text.to_owned(),
+    }
+}
+
+/// Escapes the start of a line of text that Markdown would read as a heading, list item, quote,
+/// code fence, horizontal rule or the underline of a heading. Other text (like `*emphasis*`) is
+/// kept as it is.
+fn escape_line_start(text: &str) -> String {
+    let is_underline =
+        !text.is_empty() && (text.chars().all(|c| c == '=') || text.chars().all(|c| c == '-'));
+    let starts_block = text.starts_with('>')
+        || parse_atx_heading(text).is_some()
+        || parse_list_item(text).is_some()
+        || opening_fence(text).is_some()
+        || is_horizontal_rule(text)
+        || is_underline;
+    if !starts_block {
+        return text.to_owned();
+    }
+
+    // The punctuation is escaped, which follows the number of an ordered list item
+    let num_digits = text.chars().take_while(char::is_ascii_digit).count();
+    format!("{}\\{}", &text[..num_digits], &text[num_digits..])
+}
+
+/// Returns a fence for a code block with `lines`, which is longer than any run of backticks that
+/// a line starts with
+fn code_fence(lines: &[&str]) -> String {
+    let longest_run = lines
+        .iter()
+        .map(|line| line.trim_start().chars().take_while(|&c| c == '`').count())
+        .max()
+        .unwrap_or(0);
+
+    "`".repeat(longest_run.max(2) + 1)
+}
@@ -0,0 +1,754 @@
+use crate::tree::single_line;
+use crate::{
+    consts, parse_document_with_config, Block, Config, Diagnostic, Document, FormattedLine,
+    LineEnding, LineType, RawLine, Section, Tree,
+};
+
+/// The indenting of the elements of an exported OPML document, per level
+const XML_INDENT: &str = "  ";
+
+/// An item of a list, together with the lines it was wrapped onto
+struct ListItem {
+    /// The number of list items the item is nested in
+    nesting: usize,
+    text: String,
+    /// Whether the item is checked, for TODO items
+    is_checked: Option<bool>,
+}
+
+/// An outline below the note of a section
+enum BodyOutline {
+    ListItem(ListItem),
+    /// A line that comes after a list item, which can't be part of the note
+    Text(String),
+}
+
+impl BodyOutline {
+    /// The number of list items the outline is nested in, which is 0 for text
+    fn nesting(&self) -> usize {
+        match self {
+            BodyOutline::ListItem(item) => item.nesting,
+            BodyOutline::Text(_) => 0,
+        }
+    }
+}
+
+/// Converts a parsed document to OPML 2.0, the format that outliners use for exchanging outlines.
+///
+/// Every header becomes an `<outline>` element that contains the outlines of the headers nested
+/// under it. The text, quotes and fenced filetype blocks of a header are kept (in outlaw syntax) in
+/// its `_note` attribute. Bullet points and TODO items become outlines of their own, and TODO
+/// items get a `_status` attribute of `checked` or `unchecked`. The lines after the first list
+/// item of a header, and the text above the first header, become outlines of their lines. Every
+/// outline has a `_type` attribute (`header`, `bullet`, `todo` or `text`), so that it can't be
+/// mistaken for something else when it is imported.
+pub fn to_opml(document: &Document) -> String {
+    to_opml_with_config(document, &Config::default())
+}
+
+/// Like `to_opml()`, but uses the prefixes of `config.syntax` and ends lines like
+/// `format_to_string_with_config()` does
+pub fn to_opml_with_config(document: &Document, config: &Config) -> String {
+    let tree = Tree::from_document_with_syntax(document, &config.syntax);
+    let title = tree
+        .root
+        .subsections()
+        .first()
+        .and_then(Section::title)
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or("Outline");
+
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_owned(),
+        r#"<opml version="2.0">"#.to_owned(),
+        format!("{}<head>", XML_INDENT),
+        format!("{0}{0}<title>{1}</title>", XML_INDENT, escape_xml(title)),
+        format!("{}</head>", XML_INDENT),
+        format!("{}<body>", XML_INDENT),
+    ];
+
+    // The root has no outline of its own to put a note in
+    let (note_lines, outlines) = split_body(tree.root.body(), config);
+    let note_outlines = note_lines.into_iter().map(BodyOutline::Text);
+    let outlines: Vec<BodyOutline> = note_outlines.chain(outlines).collect();
+    write_body_outlines(&mut lines, &outlines, 2);
+
+    for section in tree.root.subsections() {
+        write_section(&mut lines, section, 2, config);
+    }
+
+    lines.push(format!("{}</body>", XML_INDENT));
+    lines.push("</opml>".to_owned());
+
+    let linebreak = config.line_ending.unwrap_or(document.line_ending).as_str();
+    let mut opml = lines.join(linebreak);
+    if config.final_newline {
+        opml += linebreak;
+    }
+
+    opml
+}
+
+fn write_section(lines: &mut Vec<String>, section: &Section, depth: usize, config: &Config) {
+    let (note_lines, outlines) = split_body(section.body(), config);
+
+    let mut attributes = format!(
+        r#"text="{}" _type="header""#,
+        escape_xml(section.title().unwrap_or_default().trim())
+    );
+    if !note_lines.is_empty() {
+        attributes += &format!(r#" _note="{}""#, escape_xml(&note_lines.join("\n")));
+    }
+
+    let indent = XML_INDENT.repeat(depth);
+    if outlines.is_empty() && section.subsections().is_empty() {
+        lines.push(format!("{}<outline {}/>", indent, attributes));
+        return;
+    }
+
+    lines.push(format!("{}<outline {}>", indent, attributes));
+    write_body_outlines(lines, &outlines, depth + 1);
+    for subsection in section.subsections() {
+        write_section(lines, subsection, depth + 1, config);
+    }
+    lines.push(format!("{}</outline>", indent));
+}
+
+fn write_body_outlines(lines: &mut Vec<String>, outlines: &[BodyOutline], depth: usize) {
+    let mut num_open_items = 0;
+
+    for (index, outline) in outlines.iter().enumerate() {
+        while num_open_items > outline.nesting() {
+            num_open_items -= 1;
+            lines.push(format!(
+                "{}</outline>",
+                XML_INDENT.repeat(depth + num_open_items)
+            ));
+        }
+
+        let attributes = match outline {
+            BodyOutline::Text(text) => format!(r#"text="{}" _type="text""#, escape_xml(text)),
+            BodyOutline::ListItem(ListItem {
+                text,
+                is_checked: Some(is_checked),
+                ..
+            }) => format!(
+                r#"text="{}" _type="todo" _status="{}""#,
+                escape_xml(text),
+                if *is_checked { "checked" } else { "unchecked" }
+            ),
+            BodyOutline::ListItem(ListItem { text, .. }) => {
+                format!(r#"text="{}" _type="bullet""#, escape_xml(text))
+            }
+        };
+
+        // An item stays open when the items after it are nested in it
+        let indent = XML_INDENT.repeat(depth + outline.nesting());
+        let has_nested_items = outlines.get(index + 1).map_or(false, |next_outline| {
+            next_outline.nesting() > outline.nesting()
+        });
+        if has_nested_items {
+            lines.push(format!("{}<outline {}>", indent, attributes));
+            num_open_items += 1;
+        } else {
+            lines.push(format!("{}<outline {}/>", indent, attributes));
+        }
+    }
+
+    while num_open_items > 0 {
+        num_open_items -= 1;
+        lines.push(format!(
+            "{}</outline>",
+            XML_INDENT.repeat(depth + num_open_items)
+        ));
+    }
+}
+
+/// Splits the body of a section into the lines of its note, which are indented relative to the
+/// section, and the outlines below it. The note ends at the first list item, so that the lines
+/// after it (which become outlines of their own) keep their place between the lists.
+fn split_body(body: &[FormattedLine], config: &Config) -> (Vec<String>, Vec<BodyOutline>) {
+    let mut note_lines: Vec<String> = Vec::new();
+    let mut outlines: Vec<BodyOutline> = Vec::new();
+    // The indent levels of the list items that the last list item is nested in, followed by its
+    // own indent level
+    let mut indent_levels: Vec<usize> = Vec::new();
+
+    for line in body {
+        match line.line_type {
+            LineType::ListBulletPoint | LineType::ListTodoItem => {
+                // The outlines are separated anyway, so the blank lines above the item are
+                // dropped
+                while outlines.last().map_or(
+                    false,
+                    |outline| matches!(outline, BodyOutline::Text(text) if text.is_empty()),
+                ) {
+                    outlines.pop();
+                }
+
+                while indent_levels
+                    .last()
+                    .map_or(false, |&level| level > line.indent_level)
+                {
+                    indent_levels.pop();
+                }
+                if indent_levels
+                    .last()
+                    .map_or(true, |&level| level < line.indent_level)
+                {
+                    indent_levels.push(line.indent_level);
+                }
+
+                let prefix_length = line.line_type.get_prefix_length(&config.syntax);
+                outlines.push(BodyOutline::ListItem(ListItem {
+                    nesting: indent_levels.len() - 1,
+                    text: line.contents.get(prefix_length..).unwrap_or("").to_owned(),
+                    is_checked: (line.line_type == LineType::ListTodoItem)
+                        .then(|| line.is_checked_todo_item()),
+                }));
+            }
+            LineType::ListContinuousLine
+                if matches!(outlines.last(), Some(BodyOutline::ListItem(_))) =>
+            {
+                if let Some(BodyOutline::ListItem(item)) = outlines.last_mut() {
+                    item.text = format!("{} {}", item.text, line.contents.trim());
+                }
+            }
+            // Runs of blank lines (which can be left over from the lists) are shortened to one,
+            // and the blank lines right after a list item are dropped
+            _ if line.is_empty() && !line.line_type.is_preformatted() => {
+                if outlines.is_empty() {
+                    if note_lines.last().map_or(false, |line| !line.is_empty()) {
+                        note_lines.push(String::new());
+                    }
+                } else if matches!(outlines.last(), Some(BodyOutline::Text(text)) if !text.is_empty())
+                {
+                    outlines.push(BodyOutline::Text(String::new()));
+                }
+            }
+            _ => {
+                let indenting = " ".repeat(line.indent_level * consts::INDENT_SHIFT);
+                let text = format!("{}{}", indenting, line.contents);
+                if outlines.is_empty() {
+                    note_lines.push(text);
+                } else {
+                    outlines.push(BodyOutline::Text(text));
+                }
+            }
+        }
+    }
+
+    while note_lines.last().map_or(false, String::is_empty) {
+        note_lines.pop();
+    }
+    while outlines.last().map_or(
+        false,
+        |outline| matches!(outline, BodyOutline::Text(text) if text.is_empty()),
+    ) {
+        outlines.pop();
+    }
+
+    (note_lines, outlines)
+}
+
+/// Converts an OPML document to an outlaw document, which can be formatted with
+/// `Pipeline::format_document()`. This is the reverse of `to_opml()`, but it also accepts the
+/// OPML of other outliners:
+///
+/// * An outline with a `_type` of `header`, `bullet`, `todo` or `text` becomes a header, bullet
+///   point, TODO item or line of text, and an outline with a `_status` of `checked` or
+///   `unchecked` a TODO item.
+/// * Other outlines become a header when they contain other outlines or have a `_note`, and a
+///   bullet point otherwise. Outlines nested in a list item are always list items.
+/// * The `_note` of a header is read as the (outlaw) text below it.
+///
+/// Returns an error when the OPML isn't well-formed XML or has no `<body>`.
+pub fn from_opml(opml: &str) -> Result<Document, Diagnostic> {
+    from_opml_with_config(opml, &Config::default())
+}
+
+/// Like `from_opml()`, but reads notes like `parse_document_with_config()` does and uses the
+/// prefixes of `config.syntax`
+pub fn from_opml_with_config(opml: &str, config: &Config) -> Result<Document, Diagnostic> {
+    let root = XmlReader::new(opml).read()?;
+    if root.name != "opml" {
+        return Err(Diagnostic::error(
+            Some(root.line_number),
+            format!("expected an <opml> element, found <{}>", root.name),
+        ));
+    }
+    let body = root
+        .children
+        .iter()
+        .find(|element| element.name == "body")
+        .ok_or_else(|| Diagnostic::error(Some(root.line_number), "the OPML has no <body>"))?;
+
+    let mut document = Document::new();
+    if let Some(line_ending) = LineEnding::detect(opml) {
+        document.line_ending = line_ending;
+    }
+
+    add_outlines(&mut document, &body.children, 0, config);
+
+    Ok(document)
+}
+
+/// What an `<outline>` element turns into
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum OutlineKind {
+    Header,
+    Text,
+    BulletPoint,
+    TodoItem { is_checked: bool },
+}
+
+impl OutlineKind {
+    fn of(outline: &Element, is_in_list: bool) -> Self {
+        let status = match outline.attribute("_status") {
+            Some("checked") => Some(true),
+            Some("unchecked") => Some(false),
+            _ => None,
+        };
+
+        match (outline.attribute("_type"), status) {
+            (Some("todo"), _) | (_, Some(_)) => OutlineKind::TodoItem {
+                is_checked: status.unwrap_or(false),
+            },
+            (Some("bullet"), _) => OutlineKind::BulletPoint,
+            (Some("header"), _) if !is_in_list => OutlineKind::Header,
+            (Some("text"), _) if !is_in_list => OutlineKind::Text,
+            _ if is_in_list => OutlineKind::BulletPoint,
+            _ if !outline.outlines().is_empty() || outline.attribute("_note").is_some() => {
+                OutlineKind::Header
+            }
+            _ => OutlineKind::BulletPoint,
+        }
+    }
+}
+
+/// Adds the outlines to the last block of `document`, or (for headers) as new blocks below it.
+/// `depth` is the number of headers the outlines are nested in.
+fn add_outlines(document: &mut Document, outlines: &[Element], depth: usize, config: &Config) {
+    let outlines: Vec<&Element> = outlines
+        .iter()
+        .filter(|element| element.name == "outline")
+        .collect();
+
+    // The lines of a block come before the blocks of its subsections
+    let mut last_kind = None;
+    let mut index = 0;
+    while index < outlines.len() {
+        let outline = outlines[index];
+        let kind = OutlineKind::of(outline, false);
+        if kind == OutlineKind::Header {
+            index += 1;
+            continue;
+        }
+
+        let is_list_item = kind != OutlineKind::Text;
+        if last_kind.map_or(false, |was_list_item| was_list_item != is_list_item) {
+            document.last_block_mut().add_line(FormattedLine::empty());
+        }
+        last_kind = Some(is_list_item);
+
+        if is_list_item {
+            add_list_item(document, outline, kind, depth, config);
+            index += 1;
+        } else {
+            // Consecutive lines of text are read together, since they can be the lines of a
+            // fenced filetype block
+            let text = outlines[index..]
+                .iter()
+                .take_while(|outline| OutlineKind::of(outline, false) == OutlineKind::Text)
+                .map(|outline| outline.attribute("text").unwrap_or_default())
+                .collect::<Vec<&str>>();
+
+            add_note(
+                document,
+                &text.join("\n"),
+                outline.line_number,
+                depth,
+                config,
+            );
+            index += text.len();
+        }
+    }
+
+    for outline in outlines {
+        if OutlineKind::of(outline, false) != OutlineKind::Header {
+            continue;
+        }
+
+        let title = outline.attribute("text").unwrap_or_default();
+        let header = FormattedLine {
+            contents: format!("{}{}", config.syntax.header.canonical, single_line(title)),
+            indent_level: depth,
+            line_type: LineType::Header,
+            original_raw: raw_line_at(outline.line_number),
+        };
+        document.add_block(Block::new(header));
+
+        if let Some(note) = outline.attribute("_note") {
+            add_note(document, note, outline.line_number, depth + 1, config);
+            if !outline.outlines().is_empty() {
+                document.last_block_mut().add_line(FormattedLine::empty());
+            }
+        }
+        add_outlines(document, &outline.children, depth + 1, config);
+    }
+}
+
+/// Adds a list item and the items nested in it. `indent_level` is the indent level of the item.
+fn add_list_item(
+    document: &mut Document,
+    outline: &Element,
+    kind: OutlineKind,
+    indent_level: usize,
+    config: &Config,
+) {
+    let text = single_line(outline.attribute("text").unwrap_or_default());
+    let (line_type, contents) = match kind {
+        OutlineKind::TodoItem { is_checked } => (
+            LineType::ListTodoItem,
+            format!("[{}] {}", if is_checked { 'x' } else { ' ' }, text),
+        ),
+        _ => (
+            LineType::ListBulletPoint,
+            format!("{}{}", config.syntax.bullet_point.canonical, text),
+        ),
+    };
+
+    document.last_block_mut().add_line(FormattedLine {
+        contents: contents.trim_end().to_owned(),
+        indent_level,
+        line_type,
+        original_raw: raw_line_at(outline.line_number),
+    });
+
+    for nested_outline in outline.outlines() {
+        let kind = OutlineKind::of(nested_outline, true);
+        add_list_item(document, nested_outline, kind, indent_level + 1, config);
+    }
+}
+
+/// Adds the lines of a note (or of the text of a `text` outline) at `indent_level`. The lines are
+/// parsed like the lines of a document, so they keep their type and relative indenting.
+fn add_note(
+    document: &mut Document,
+    note: &str,
+    line_number: usize,
+    indent_level: usize,
+    config: &Config,
+) {
+    let parsed = parse_document_with_config(note, config);
+
+    for block in parsed.blocks {
+        // A note can't contain headers, so a line that looks like one is kept as text
+        let header = block
+            .header()
+            .filter(|header| !header.is_empty())
+            .map(|header| FormattedLine {
+                line_type: LineType::Text,
+                ..header.clone()
+            });
+
+        for line in header.into_iter().chain(block.contents) {
+            document.last_block_mut().add_line(FormattedLine {
+                indent_level: line.indent_level + indent_level,
+                original_raw: raw_line_at(line_number),
+                ..line
+            });
+        }
+    }
+}
+
+/// Returns an empty `RawLine` with the number of the line of the OPML an outline started on
+fn raw_line_at(line_number: usize) -> RawLine {
+    RawLine {
+        line_number: Some(line_number),
+        ..RawLine::default()
+    }
+}
+
+/// Replaces the characters that can't be used as is in XML text and attribute values
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            // Line breaks and tabs in attribute values would be read back as spaces
+            '\n' => escaped += "&#10;",
+            '\r' => escaped += "&#13;",
+            '\t' => escaped += "&#9;",
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// An element of an XML document. Its text is left out, since OPML keeps everything in attributes.
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    /// The line of the document the element starts on
+    line_number: usize,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute_name, _)| attribute_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn outlines(&self) -> Vec<&Element> {
+        self.children
+            .iter()
+            .filter(|element| element.name == "outline")
+            .collect()
+    }
+}
+
+/// Reads the elements of an XML document. This is just enough XML for OPML: comments, processing
+/// instructions, CDATA sections and doctypes are skipped, and the standard entities and character
+/// references are decoded.
+struct XmlReader<'a> {
+    xml: &'a str,
+    position: usize,
+    line_number: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn new(xml: &'a str) -> Self {
+        XmlReader {
+            xml: xml.strip_prefix(consts::BYTE_ORDER_MARK).unwrap_or(xml),
+            position: 0,
+            line_number: 1,
+        }
+    }
+
+    /// Returns the root element of the document
+    fn read(mut self) -> Result<Element, Diagnostic> {
+        // The elements that were opened but not closed yet, from the root down
+        let mut open_elements: Vec<Element> = Vec::new();
+
+        while let Some(offset) = self.rest().find('<') {
+            self.advance(offset);
+
+            let skipped_until = [
+                ("<?", "?>"),
+                ("<!--", "-->"),
+                ("<![CDATA[", "]]>"),
+                ("<!", ">"),
+            ]
+            .into_iter()
+            .find(|(start, _)| self.rest().starts_with(start));
+            if let Some((_, end)) = skipped_until {
+                self.skip_past(end)?;
+                continue;
+            }
+
+            if self.rest().starts_with("</") {
+                let line_number = self.line_number;
+                self.advance(2);
+                let name = self.read_name();
+                self.skip_whitespace();
+                self.expect('>')?;
+
+                let element = match open_elements.pop() {
+                    Some(element) if element.name == name => element,
+                    Some(element) => {
+                        return Err(Diagnostic::error(
+                            Some(line_number),
+                            format!(
+                                "expected </{}> for the element on line {}, found </{}>",
+                                element.name, element.line_number, name
+                            ),
+                        ))
+                    }
+                    None => {
+                        return Err(Diagnostic::error(
+                            Some(line_number),
+                            format!("</{}> closes an element that was never opened", name),
+                        ))
+                    }
+                };
+
+                match open_elements.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            } else {
+                let (element, is_empty) = self.read_start_tag()?;
+                match open_elements.last_mut() {
+                    Some(parent) if is_empty => parent.children.push(element),
+                    None if is_empty => return Ok(element),
+                    _ => open_elements.push(element),
+                }
+            }
+        }
+
+        match open_elements.pop() {
+            Some(element) => Err(Diagnostic::error(
+                Some(element.line_number),
+                format!("<{}> is never closed", element.name),
+            )),
+            None => Err(Diagnostic::error(None, "the document has no elements")),
+        }
+    }
+
+    /// Reads a tag like `<outline text="Title">`, and returns whether it is an empty element tag
+    /// (ending with `/>`)
+    fn read_start_tag(&mut self) -> Result<(Element, bool), Diagnostic> {
+        let line_number = self.line_number;
+        self.advance(1);
+
+        let mut element = Element {
+            name: self.read_name(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            line_number,
+        };
+        if element.name.is_empty() {
+            return Err(self.error("expected the name of an element after <"));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.advance(2);
+                return Ok((element, true));
+            }
+            if self.rest().starts_with('>') {
+                self.advance(1);
+                return Ok((element, false));
+            }
+
+            let name = self.read_name();
+            if name.is_empty() {
+                return Err(self.error(format!("unexpected character in <{}>", element.name)));
+            }
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error(format!("expected a quoted value for {}", name))),
+            };
+            self.advance(1);
+            let length = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error(format!("the value of {} is never closed", name)))?;
+            let value_line_number = self.line_number;
+            let value = decode_attribute(&self.rest()[..length])
+                .map_err(|message| Diagnostic::error(Some(value_line_number), message))?;
+            self.advance(length + 1);
+
+            element.attributes.push((name, value));
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.xml[self.position..]
+    }
+
+    fn advance(&mut self, length: usize) {
+        self.line_number += self.rest()[..length].matches('\n').count();
+        self.position += length;
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), Diagnostic> {
+        let offset = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| self.error(format!("expected {}", end)))?;
+        self.advance(offset + end.len());
+
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let length = self.rest().len() - self.rest().trim_start().len();
+        self.advance(length);
+    }
+
+    fn read_name(&mut self) -> String {
+        let length = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '/' | '>' | '<'))
+            .unwrap_or(self.rest().len());
+        let name = self.rest()[..length].to_owned();
+        self.advance(length);
+
+        name
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Diagnostic> {
+        if !self.rest().starts_with(c) {
+            return Err(self.error(format!("expected {}", c)));
+        }
+
+        self.advance(1);
+        Ok(())
+    }
+
+    fn error(&self, message: impl Into<String>) -> Diagnostic {
+        Diagnostic::error(Some(self.line_number), message)
+    }
+}
+
+/// Decodes the entities and character references of an attribute value. Like any XML parser, it
+/// turns line breaks and tabs that are written as is into spaces.
+fn decode_attribute(value: &str) -> Result<String, String> {
+    let value = value.replace("\r\n", " ");
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value.as_str();
+
+    while let Some(position) = rest.find(['&', '\n', '\r', '\t']) {
+        decoded += &rest[..position];
+        rest = &rest[position..];
+
+        if !rest.starts_with('&') {
+            decoded.push(' ');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let end = rest
+            .find(';')
+            .ok_or("& has to be written as &amp; in an attribute value")?;
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code_point = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity
+                        .strip_prefix('#')
+                        .and_then(|decimal| decimal.parse().ok()),
+                };
+                code_point.and_then(char::from_u32)
+            }
+        };
+
+        decoded.push(c.ok_or_else(|| format!("&{}; is not a valid entity", entity))?);
+        rest = &rest[end + 1..];
+    }
+
+    decoded += rest;
+    Ok(decoded)
+}
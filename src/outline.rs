@@ -0,0 +1,133 @@
+use crate::{
+    format_to_string_with_config, wrap_long_lines_with_config, Config, Document, EditError,
+    LineEnding, Section, Syntax, Tree,
+};
+
+/// Builds a document from code, without having to know how its lines are indented:
+///
+/// ```
+/// use outlaw_format::Outline;
+///
+/// let text = Outline::new()
+///     .section("Plans", |plans| {
+///         plans
+///             .text("What we want to do next.")
+///             .todo("Ship it", false)
+///             .section("Later", |later| later.list_item("Maybe"))
+///     })
+///     .render()?;
+/// # Ok::<(), outlaw_format::EditError>(())
+/// ```
+///
+/// Text that can't be added is remembered instead of added, and returned as an error when the
+/// outline is turned into a tree, document or text.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    section: Section,
+    syntax: Syntax,
+    error: Option<EditError>,
+}
+
+impl Outline {
+    pub fn new() -> Self {
+        Self::with_syntax(Syntax::default())
+    }
+
+    /// Like `new()`, but uses the prefixes of `syntax` for headers and bullet points
+    pub fn with_syntax(syntax: Syntax) -> Self {
+        Outline {
+            section: Section::default(),
+            syntax,
+            error: None,
+        }
+    }
+
+    /// Adds text, where every line of `text` becomes a line of the document. Text that is added
+    /// to the outline itself ends up above the first header.
+    ///
+    /// When a line of `text` would be read as a header, list item or fence marker (like
+    /// `* not a list`), none of `text` is added, and the outline can't be rendered. Use
+    /// `try_text()` to handle such text right away.
+    pub fn text(mut self, text: &str) -> Self {
+        if let Err(error) = self.section.push_text(text, &self.syntax) {
+            self.error = self.error.or(Some(error));
+        }
+        self
+    }
+
+    /// Like `text()`, but fails when a line of `text` would be read as a header, list item or fence
+    /// marker
+    pub fn try_text(mut self, text: &str) -> Result<Self, EditError> {
+        self.section.push_text(text, &self.syntax)?;
+        Ok(self)
+    }
+
+    pub fn list_item(mut self, text: &str) -> Self {
+        self.section.push_list_item(text, &self.syntax);
+        self
+    }
+
+    pub fn todo(mut self, text: &str, is_checked: bool) -> Self {
+        self.section.push_todo_item(text, is_checked);
+        self
+    }
+
+    /// Adds a section with a header with `title`, where the lines of `title` are joined into one.
+    /// Its contents are added by `build`, which gets an `Outline` for the section.
+    pub fn section(mut self, title: &str, build: impl FnOnce(Outline) -> Outline) -> Self {
+        let section = build(Outline {
+            section: Section::new(title),
+            syntax: self.syntax.clone(),
+            error: None,
+        });
+
+        self.section.push_section(section.section);
+        self.error = self.error.or(section.error);
+        self
+    }
+
+    /// Fails with the error of the first text that couldn't be added
+    pub fn into_tree(self) -> Result<Tree, EditError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        Ok(Tree {
+            root: self.section,
+            syntax: self.syntax,
+            line_ending: LineEnding::native(),
+            has_byte_order_mark: false,
+        })
+    }
+
+    pub fn to_document(&self) -> Result<Document, EditError> {
+        Ok(self.clone().into_tree()?.to_document())
+    }
+
+    /// Formats the outline into the text of a document
+    pub fn render(&self) -> Result<String, EditError> {
+        let config = Config {
+            syntax: self.syntax.clone(),
+            ..Config::default()
+        };
+
+        self.render_with_config(&config)
+    }
+
+    /// Like `render()`, but formats the text with the options of `config`, like the maximum line
+    /// length and the line ending
+    pub fn render_with_config(&self, config: &Config) -> Result<String, EditError> {
+        let mut document = self.to_document()?;
+        for block in document.blocks.iter_mut() {
+            wrap_long_lines_with_config(&mut block.contents, config);
+        }
+
+        Ok(format_to_string_with_config(&document, config))
+    }
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
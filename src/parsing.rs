@@ -1,89 +1,313 @@
 use std::cmp::Ordering;
 
-use crate::{Block, Document, FormattedLine, LineType, RawLine};
+use crate::config::{PreformattedWhitespace, UnclosedFencePolicy};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::line_ending::split_lines;
+use crate::{
+    consts, strip_indent_columns, Block, Config, Document, FormattedLine, LineEnding, LineType,
+    RawLine, Syntax,
+};
 
 enum Context {
     Normal,
-    HandlingFencedFiletype { base_indent: usize },
+    HandlingFencedFiletype {
+        base_indent: usize,
+        opening_marker: FenceMarker,
+        opening_line_number: Option<usize>,
+        /// Whether the block was already reported for running into a header
+        crossed_header: bool,
+    },
+}
+
+/// The run of backticks or tildes that opens or closes a fenced filetype block
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct FenceMarker {
+    character: char,
+    length: usize,
+}
+
+impl FenceMarker {
+    const MIN_LENGTH: usize = 3;
+
+    fn from_raw(raw_line: &RawLine) -> Option<Self> {
+        let character = raw_line.trimmed.chars().next()?;
+        if character != '`' && character != '~' {
+            return None;
+        }
+
+        let length = raw_line
+            .trimmed
+            .chars()
+            .take_while(|&c| c == character)
+            .count();
+
+        (length >= Self::MIN_LENGTH).then_some(FenceMarker { character, length })
+    }
+
+    /// Like CommonMark, a block is only closed by a marker of the same kind that is at least as
+    /// long as the opening marker and isn't followed by anything else (like a filetype).
+    fn is_closed_by(&self, raw_line: &RawLine) -> bool {
+        match FenceMarker::from_raw(raw_line) {
+            Some(marker) => {
+                marker.character == self.character
+                    && marker.length >= self.length
+                    && raw_line.trimmed[marker.length..].trim().is_empty()
+            }
+            None => false,
+        }
+    }
 }
 
 /// Parses the lines of `contents` and determines the type of line (header, bullet point list,
 /// etc.) and decides the indenting each line needs to get.
 pub fn parse_document(contents: &str) -> Document {
-    let mut document = Document::new();
-
-    let mut context = Context::Normal;
+    parse_document_with_config(contents, &Config::default())
+}
 
-    for line in contents.lines() {
-        let raw_line = RawLine::from_string(line);
+pub fn parse_document_with_config(contents: &str, config: &Config) -> Document {
+    let mut document = Document::new();
 
-        if raw_line.is_header() {
-            // Finding a header means the start of a new Block
+    // The byte order mark would otherwise end up in the first line, hiding the prefix of a header
+    let contents = match contents.strip_prefix(consts::BYTE_ORDER_MARK) {
+        Some(contents) => {
+            document.has_byte_order_mark = true;
+            contents
+        }
+        None => contents,
+    };
 
-            let indent_level = determine_new_header_indent(&document, &raw_line);
-            let header = FormattedLine::from_raw(raw_line, indent_level);
+    if let Some(line_ending) = LineEnding::detect(contents) {
+        document.line_ending = line_ending;
+    }
 
-            document.add_block(Block::new(header));
-        } else if raw_line.is_list_item() {
-            // This case means that the line is either the start of a new list (bullet point or
-            // TODO items), or the continuation of one.
+    let syntax = &config.syntax;
+    let mut context = Context::Normal;
 
-            let current_block = document.last_block_mut();
-            let indent_level = determine_new_bullet_point_indent(current_block, &raw_line);
-            let bullet_point_line = FormattedLine::from_raw(raw_line, indent_level);
+    for (index, line) in split_lines(contents).enumerate() {
+        let mut raw_line = RawLine::from_string_with_tab_width(line, config.tab_width);
+        raw_line.line_number = Some(index + 1);
 
-            current_block.add_line(bullet_point_line);
-        } else if raw_line.contains_marker() {
-            // A marker for a fenced filetype was encountered. Until the marker is repeated all
-            // lines after this one should be considered to be preformatted.
+        if is_fence_boundary(&context, &raw_line) {
+            // A marker for a fenced filetype was encountered. Until a matching marker is found
+            // all lines after this one should be considered to be preformatted.
 
             context = match context {
                 Context::Normal => Context::HandlingFencedFiletype {
                     base_indent: raw_line.num_indent,
+                    opening_marker: FenceMarker::from_raw(&raw_line)
+                        .expect("line should contain a fence marker"),
+                    opening_line_number: raw_line.line_number,
+                    crossed_header: false,
                 },
                 Context::HandlingFencedFiletype { .. } => Context::Normal,
             };
 
             let current_block = document.last_block_mut();
-            let line = parse_text_line(current_block, raw_line);
+            let line = parse_text_line(current_block, raw_line, syntax);
+
+            current_block.add_line(line);
+        } else if let Some(base_indent) = fenced_content_indent(&context, &raw_line, syntax) {
+            // This is a line that is part of a preformatted range of text (e.g. code), even when
+            // it looks like a header or list item, unless it is a blank line that gets collapsed
+            // into the previous one
+
+            let current_block = document.last_block_mut();
+            if config.preformatted_whitespace == PreformattedWhitespace::CollapseBlankLines
+                && raw_line.is_empty()
+                && current_block.last_line().map_or(false, |line| {
+                    line.line_type == LineType::FencedFiletype && line.is_blank()
+                })
+            {
+                continue;
+            }
 
+            let line = parse_fenced_filetype_line(current_block, raw_line, base_indent, config);
             current_block.add_line(line);
+        } else if raw_line.is_header(syntax) {
+            // Finding a header means the start of a new Block
+
+            if let Context::HandlingFencedFiletype {
+                opening_marker,
+                opening_line_number,
+                ref mut crossed_header,
+                ..
+            } = context
+            {
+                // Headers can't be part of a fenced filetype block, the closing marker was
+                // probably forgotten
+                if !*crossed_header {
+                    document.diagnostics.push(Diagnostic::new(
+                        unclosed_fence_severity(config),
+                        opening_line_number,
+                        format!(
+                            "fenced block is not closed before the header on line {}",
+                            raw_line.line_number.unwrap_or_default()
+                        ),
+                    ));
+                    *crossed_header = true;
+                }
+
+                if config.unclosed_fence_policy == UnclosedFencePolicy::CloseAtHeader {
+                    close_fenced_filetype(document.last_block_mut(), opening_marker);
+                    context = Context::Normal;
+                }
+            }
+
+            let indent_level = determine_new_header_indent(&document, &raw_line, syntax);
+            let header = FormattedLine::from_raw_with_syntax(raw_line, indent_level, syntax);
+
+            document.add_block(Block::new(header));
+        } else if raw_line.is_list_item(syntax) {
+            // This case means that the line is either the start of a new list (bullet point or
+            // TODO items), or the continuation of one.
+
+            let current_block = document.last_block_mut();
+            let indent_level = determine_new_bullet_point_indent(current_block, &raw_line, syntax);
+            let bullet_point_line =
+                FormattedLine::from_raw_with_syntax(raw_line, indent_level, syntax);
+
+            current_block.add_line(bullet_point_line);
         } else {
             // In this case the line is either a normal line of text, some prefixed line (like a
             // quote or preformatted) or the continuation of a (line wrapped) bullet point.
 
             let current_block = document.last_block_mut();
-
-            let line = if let Context::HandlingFencedFiletype { base_indent } = context {
-                // This is a line that is part of a preformatted range of text (e.g. code)
-                //
-                // Preserve the existing indenting of the text/code in these lines that would
-                // otherwise be trimmed off.
-                FormattedLine {
-                    indent_level: current_block.contents_indent_level(),
-                    line_type: LineType::Preformatted,
-                    contents: format!(
-                        "{preformat_indent}{text}",
-                        preformat_indent =
-                            " ".repeat(raw_line.num_indent.saturating_sub(base_indent)),
-                        text = &raw_line.trimmed
-                    ),
-                    original_raw: raw_line,
-                }
-            } else {
-                parse_text_line(current_block, raw_line)
-            };
+            let line = parse_text_line(current_block, raw_line, syntax);
 
             current_block.add_line(line);
         };
     }
 
+    if let Context::HandlingFencedFiletype {
+        opening_marker,
+        opening_line_number,
+        crossed_header,
+        ..
+    } = context
+    {
+        if !crossed_header {
+            document.diagnostics.push(Diagnostic::new(
+                unclosed_fence_severity(config),
+                opening_line_number,
+                "fenced block is never closed",
+            ));
+        }
+
+        if config.unclosed_fence_policy == UnclosedFencePolicy::CloseAtHeader {
+            close_fenced_filetype(document.last_block_mut(), opening_marker);
+        }
+    }
+
     document
 }
 
+fn unclosed_fence_severity(config: &Config) -> Severity {
+    match config.unclosed_fence_policy {
+        UnclosedFencePolicy::Refuse => Severity::Error,
+        _ => Severity::Warning,
+    }
+}
+
+/// Creates the line of a fenced filetype block, preserving the existing indenting of the
+/// text/code in it that would otherwise be trimmed off.
+fn parse_fenced_filetype_line(
+    current_block: &Block,
+    raw_line: RawLine,
+    base_indent: usize,
+    config: &Config,
+) -> FormattedLine {
+    let preserve_whitespace = config.preformatted_whitespace == PreformattedWhitespace::Preserve;
+
+    if raw_line.is_empty() {
+        // Re-indenting a line with only whitespace could change it, so it is either kept exactly
+        // as it is or becomes a blank line
+        return FormattedLine {
+            indent_level: current_block.contents_indent_level(),
+            line_type: LineType::FencedFiletype,
+            contents: if preserve_whitespace {
+                raw_line.raw.clone()
+            } else {
+                String::new()
+            },
+            original_raw: raw_line,
+        };
+    }
+
+    let indent_length = raw_line.raw.len() - raw_line.raw.trim_start().len();
+    let text = if preserve_whitespace {
+        &raw_line.raw[indent_length..]
+    } else {
+        &raw_line.trimmed
+    };
+
+    FormattedLine {
+        indent_level: current_block.contents_indent_level(),
+        line_type: LineType::FencedFiletype,
+        contents: format!(
+            "{preformat_indent}{text}",
+            preformat_indent = if config.preserve_preformatted_tabs {
+                strip_indent_columns(&raw_line.raw, base_indent, config.tab_width)
+            } else {
+                " ".repeat(raw_line.num_indent.saturating_sub(base_indent))
+            },
+        ),
+        original_raw: raw_line,
+    }
+}
+
+/// Adds the closing marker of an unclosed fenced filetype block after the last non-empty line of
+/// the block.
+fn close_fenced_filetype(current_block: &mut Block, opening_marker: FenceMarker) {
+    let Some(opening_line) = current_block.find_previous_of(LineType::FencedFiletypeMarker) else {
+        return;
+    };
+
+    let closing_line = FormattedLine {
+        contents: opening_marker
+            .character
+            .to_string()
+            .repeat(opening_marker.length),
+        indent_level: opening_line.indent_level,
+        line_type: LineType::FencedFiletypeMarker,
+        original_raw: RawLine::default(),
+    };
+
+    let position = current_block
+        .contents
+        .iter()
+        .rposition(|line| !line.is_blank())
+        .map_or(0, |index| index + 1);
+    current_block.contents.insert(position, closing_line);
+}
+
+/// The indent of the opening marker when `raw_line` is part of the contents of a fenced filetype
+/// block. A header that is indented less than the marker ends up outside of the block, since the
+/// closing marker was probably forgotten.
+fn fenced_content_indent(context: &Context, raw_line: &RawLine, syntax: &Syntax) -> Option<usize> {
+    match *context {
+        Context::HandlingFencedFiletype { base_indent, .. }
+            if !(raw_line.is_header(syntax) && raw_line.num_indent < base_indent) =>
+        {
+            Some(base_indent)
+        }
+        _ => None,
+    }
+}
+
+/// Returns whether the line opens a fenced filetype block, or closes the one that is currently
+/// being handled.
+fn is_fence_boundary(context: &Context, raw_line: &RawLine) -> bool {
+    match context {
+        Context::Normal => FenceMarker::from_raw(raw_line).is_some(),
+        Context::HandlingFencedFiletype { opening_marker, .. } => {
+            opening_marker.is_closed_by(raw_line)
+        }
+    }
+}
+
 /// Determines if the given line is a child, sibling or parent of the previous block's header
-fn determine_new_header_indent(document: &Document, raw_line: &RawLine) -> usize {
-    assert!(raw_line.is_header());
+fn determine_new_header_indent(document: &Document, raw_line: &RawLine, syntax: &Syntax) -> usize {
+    assert!(raw_line.is_header(syntax));
 
     let previous_block = document.last_block();
 
@@ -102,8 +326,12 @@ fn determine_new_header_indent(document: &Document, raw_line: &RawLine) -> usize
     }
 }
 
-fn determine_new_bullet_point_indent(current_block: &Block, raw_line: &RawLine) -> usize {
-    assert!(raw_line.is_list_item());
+fn determine_new_bullet_point_indent(
+    current_block: &Block,
+    raw_line: &RawLine,
+    syntax: &Syntax,
+) -> usize {
+    assert!(raw_line.is_list_item(syntax));
 
     let previous_list_item = current_block
         .find_previous_of(LineType::ListBulletPoint)
@@ -141,7 +369,7 @@ fn determine_new_bullet_point_indent(current_block: &Block, raw_line: &RawLine)
     }
 }
 
-fn parse_text_line(current_block: &mut Block, raw_line: RawLine) -> FormattedLine {
+fn parse_text_line(current_block: &mut Block, raw_line: RawLine, syntax: &Syntax) -> FormattedLine {
     if let Some(previous_line) = current_block.last_line() {
         if previous_line.is_list_item() && !raw_line.is_empty() {
             FormattedLine {
@@ -149,23 +377,31 @@ fn parse_text_line(current_block: &mut Block, raw_line: RawLine) -> FormattedLin
                 line_type: LineType::ListContinuousLine,
                 contents: format!(
                     "{}{}",
-                    LineType::ListContinuousLine.get_prefix(),
+                    LineType::ListContinuousLine.get_prefix(syntax),
                     &raw_line.trimmed
                 ),
                 original_raw: raw_line,
             }
         } else if current_block.has_header() {
             // Non-bullet list Contents of a block follow the block's indent level plus one
-            FormattedLine::from_raw(raw_line, current_block.contents_indent_level())
+            FormattedLine::from_raw_with_syntax(
+                raw_line,
+                current_block.contents_indent_level(),
+                syntax,
+            )
         } else {
             // This applies to empty lines and to lines of text that are placed before the
             // very first header of the document.
 
-            FormattedLine::from_raw(raw_line, current_block.contents_indent_level())
+            FormattedLine::from_raw_with_syntax(
+                raw_line,
+                current_block.contents_indent_level(),
+                syntax,
+            )
         }
     } else {
         // This applies to the first line after a header.
 
-        FormattedLine::from_raw(raw_line, current_block.contents_indent_level())
+        FormattedLine::from_raw_with_syntax(raw_line, current_block.contents_indent_level(), syntax)
     }
 }
@@ -0,0 +1,379 @@
+use crate::config::PreformattedStyle;
+use crate::{
+    align_tables, check_fence_languages, convert_preformatted_style, format_to_string_with_config,
+    parse_document_with_config, run_fence_formatters, update_completion_stamps,
+    wrap_long_lines_with_config, Config, Document, Formatted, FormattedLine, LineType, Severity,
+};
+
+/// A step of formatting that changes a parsed document, like wrapping long lines. Problems can
+/// be reported by adding them to `document.diagnostics`.
+pub trait Pass {
+    /// A short name that identifies the pass in a `Pipeline`, e.g. `wrap-long-lines`
+    fn name(&self) -> &str;
+
+    fn run(&self, document: &mut Document, config: &Config);
+}
+
+/// The passes that are run on a parsed document before it is printed, in the order they are
+/// run
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline without any passes, which only parses and prints documents
+    pub fn new() -> Self {
+        Pipeline { passes: Vec::new() }
+    }
+
+    /// Creates the pipeline used by `format_with_config()`, with the passes that are enabled in
+    /// `config`
+    pub fn from_config(config: &Config) -> Self {
+        let mut pipeline = Pipeline::new();
+
+        // Adding or removing completion stamps of TODO items, and moving checked items down
+        if config.completion_stamp.is_some() {
+            pipeline.add(UpdateCompletionStamps);
+        }
+        if config.sort_todo_items {
+            pipeline.add(SortTodoItems);
+        }
+
+        // Aligning the columns of tables
+        if config.align_tables {
+            pipeline.add(AlignTables);
+        }
+
+        // Converting between preformatted lines and fenced filetype blocks, and normalizing and
+        // checking the filetypes of fenced filetype blocks
+        if config.preformatted_style != PreformattedStyle::Keep {
+            pipeline.add(ConvertPreformattedStyle);
+        }
+        pipeline.add(CheckFenceLanguages);
+
+        if config.number_headers {
+            pipeline.add(NumberHeaders);
+        }
+
+        // Reformatting the contents of fenced filetype blocks with external commands
+        if !config.fence_formatters.is_empty() {
+            pipeline.add(RunFenceFormatters);
+        }
+
+        // Wrapping long lines comes last, since the other passes can make lines longer
+        pipeline.add(WrapLongLines);
+
+        pipeline
+    }
+
+    /// Adds a pass that runs after all passes that were added before it
+    pub fn add(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Adds a pass that runs right before the pass named `name`, or after all other passes if
+    /// there is no such pass
+    pub fn insert_before(&mut self, name: &str, pass: impl Pass + 'static) {
+        let index = self
+            .passes
+            .iter()
+            .position(|existing_pass| existing_pass.name() == name)
+            .unwrap_or(self.passes.len());
+
+        self.passes.insert(index, Box::new(pass));
+    }
+
+    /// Removes the pass named `name`, and returns whether there was one
+    pub fn remove(&mut self, name: &str) -> bool {
+        let num_passes = self.passes.len();
+        self.passes.retain(|pass| pass.name() != name);
+
+        self.passes.len() != num_passes
+    }
+
+    /// The names of the passes, in the order they are run
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs all passes on `document`
+    pub fn run(&self, document: &mut Document, config: &Config) {
+        for pass in self.passes.iter() {
+            pass.run(document, config);
+        }
+    }
+
+    /// Parses `contents`, runs all passes on it and prints the result. A document that has
+    /// errors after parsing is left as it is.
+    pub fn format(&self, contents: &str, config: &Config) -> Formatted {
+        let document = parse_document_with_config(contents, config);
+
+        if document
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            // The document can't be formatted reliably, so leave it as it is
+            return Formatted {
+                text: contents.to_owned(),
+                diagnostics: document.diagnostics,
+            };
+        }
+
+        self.format_document(document, config)
+    }
+
+    /// Runs all passes on a document that was parsed or built in another way (like with
+    /// `from_markdown()`) and prints the result
+    pub fn format_document(&self, mut document: Document, config: &Config) -> Formatted {
+        self.run(&mut document, config);
+
+        Formatted {
+            text: format_to_string_with_config(&document, config),
+            diagnostics: document.diagnostics,
+        }
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// See `update_completion_stamps()`, only does something when `config.completion_stamp` is set
+/// to a marker that isn't empty
+pub struct UpdateCompletionStamps;
+
+impl Pass for UpdateCompletionStamps {
+    fn name(&self) -> &str {
+        "update-completion-stamps"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        let Some(marker) = config
+            .completion_stamp
+            .as_deref()
+            .filter(|marker| !marker.trim().is_empty())
+        else {
+            return;
+        };
+        let today = config.clock.today();
+
+        for block in document.blocks.iter_mut() {
+            update_completion_stamps(&mut block.contents, marker, today);
+        }
+    }
+}
+
+/// Moves checked TODO items below the unchecked ones of the same list, keeping the order of the
+/// items otherwise
+pub struct SortTodoItems;
+
+impl Pass for SortTodoItems {
+    fn name(&self) -> &str {
+        "sort-todo-items"
+    }
+
+    fn run(&self, document: &mut Document, _config: &Config) {
+        for block in document.blocks.iter_mut() {
+            sort_todo_items(&mut block.contents);
+        }
+    }
+}
+
+/// See `align_tables()`
+pub struct AlignTables;
+
+impl Pass for AlignTables {
+    fn name(&self) -> &str {
+        "align-tables"
+    }
+
+    fn run(&self, document: &mut Document, _config: &Config) {
+        for block in document.blocks.iter_mut() {
+            align_tables(&mut block.contents);
+        }
+    }
+}
+
+/// See `convert_preformatted_style()`
+pub struct ConvertPreformattedStyle;
+
+impl Pass for ConvertPreformattedStyle {
+    fn name(&self) -> &str {
+        "convert-preformatted-style"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        for block in document.blocks.iter_mut() {
+            convert_preformatted_style(&mut block.contents, config);
+        }
+    }
+}
+
+/// See `check_fence_languages()`
+pub struct CheckFenceLanguages;
+
+impl Pass for CheckFenceLanguages {
+    fn name(&self) -> &str {
+        "check-fence-languages"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        for block_index in 0..document.blocks.len() {
+            let diagnostics =
+                check_fence_languages(&mut document.blocks[block_index].contents, config);
+            document.diagnostics.extend(diagnostics);
+        }
+    }
+}
+
+/// Puts the number of every header in front of its title, e.g. `=== 2.1. Title` for the first
+/// subheader of the second header. Numbers that were added before are replaced when they have one
+/// part for every level of the header. This is ambiguous for titles that start with a number
+/// themselves: the `1984. ` of a top level header titled `1984. Orwell` is replaced as well.
+pub struct NumberHeaders;
+
+impl Pass for NumberHeaders {
+    fn name(&self) -> &str {
+        "number-headers"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        let mut header_numbers: Vec<Vec<usize>> = Vec::new();
+        let mut numbers: Vec<usize> = Vec::new();
+        for block in document.blocks.iter().filter(|block| block.has_header()) {
+            let indent_level = block.header.indent_level;
+            numbers.truncate(indent_level + 1);
+            numbers.resize(indent_level + 1, 0);
+            *numbers
+                .last_mut()
+                .expect("there should be a number for the header") += 1;
+            header_numbers.push(numbers.clone());
+        }
+
+        for (block, numbers) in document
+            .blocks
+            .iter_mut()
+            .filter(|block| block.has_header())
+            .zip(header_numbers)
+        {
+            let header = &mut block.header;
+            let prefix_length = header.line_type.get_prefix_length(&config.syntax);
+            let (prefix, title) = header.contents.split_at(prefix_length);
+
+            // A number that was added before has a part for every level of the header
+            let title = match split_header_number(title) {
+                Some((old_numbers, rest)) if old_numbers.len() == numbers.len() => rest,
+                _ => title,
+            };
+
+            header.contents = format!(
+                "{prefix}{number}. {title}",
+                number = numbers
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            );
+        }
+    }
+}
+
+/// See `run_fence_formatters()`, only does something when `config.fence_formatters` isn't empty
+pub struct RunFenceFormatters;
+
+impl Pass for RunFenceFormatters {
+    fn name(&self) -> &str {
+        "run-fence-formatters"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        if config.fence_formatters.is_empty() {
+            return;
+        }
+
+        for block_index in 0..document.blocks.len() {
+            let diagnostics = run_fence_formatters(
+                &mut document.blocks[block_index].contents,
+                &config.fence_formatters,
+            );
+            document.diagnostics.extend(diagnostics);
+        }
+    }
+}
+
+/// See `wrap_long_lines_with_config()`
+pub struct WrapLongLines;
+
+impl Pass for WrapLongLines {
+    fn name(&self) -> &str {
+        "wrap-long-lines"
+    }
+
+    fn run(&self, document: &mut Document, config: &Config) {
+        for block in document.blocks.iter_mut() {
+            wrap_long_lines_with_config(&mut block.contents, config)
+        }
+    }
+}
+
+fn sort_todo_items(formatted_lines: &mut [FormattedLine]) {
+    let mut index = 0;
+    while index < formatted_lines.len() {
+        if formatted_lines[index].line_type != LineType::ListTodoItem {
+            index += 1;
+            continue;
+        }
+
+        // Find the items of the list, together with their wrapped lines and nested lists
+        let indent_level = formatted_lines[index].indent_level;
+        let mut items = Vec::new();
+        let mut end = index;
+        while formatted_lines.get(end).map_or(false, |line| {
+            line.line_type == LineType::ListTodoItem && line.indent_level == indent_level
+        }) {
+            let item_length = 1 + formatted_lines[end + 1..]
+                .iter()
+                .take_while(|line| {
+                    line.is_list_item()
+                        && (line.indent_level > indent_level
+                            || line.line_type == LineType::ListContinuousLine)
+                })
+                .count();
+
+            items.push(formatted_lines[end..end + item_length].to_vec());
+            end += item_length;
+        }
+
+        // A stable sort keeps the order of the items that are (un)checked
+        items.sort_by_key(|item| item[0].is_checked_todo_item());
+        for (line, sorted_line) in formatted_lines[index..end]
+            .iter_mut()
+            .zip(items.into_iter().flatten())
+        {
+            *line = sorted_line;
+        }
+
+        index = end;
+    }
+}
+
+/// Splits a number like `2.1. ` off the start of a title, which can have been put there by
+/// `NumberHeaders`
+fn split_header_number(title: &str) -> Option<(Vec<usize>, &str)> {
+    let (number, rest) = title.split_once(". ")?;
+    let numbers = number
+        .split('.')
+        .map(|part| {
+            if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) {
+                part.parse().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<usize>>>()?;
+
+    Some((numbers, rest))
+}
@@ -0,0 +1,106 @@
+use crate::{consts, LineType};
+
+/// A prefix that marks the type of a line, e.g. `=== ` for headers.
+///
+/// Lines starting with one of the `alternatives` are accepted as well, and get their prefix
+/// replaced with the `canonical` one when formatting.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Prefix {
+    pub canonical: String,
+    pub alternatives: Vec<String>,
+}
+
+impl Prefix {
+    pub fn new(canonical: &str) -> Self {
+        Prefix {
+            canonical: canonical.to_owned(),
+            alternatives: Vec::new(),
+        }
+    }
+
+    /// Returns the length of the prefix `line` starts with, if any
+    fn find_in(&self, line: &str) -> Option<usize> {
+        std::iter::once(&self.canonical)
+            .chain(self.alternatives.iter())
+            .filter(|prefix| !prefix.is_empty() && line.starts_with(prefix.as_str()))
+            .map(|prefix| prefix.len())
+            .max()
+    }
+
+    /// Like `find_in()`, but also accepts a line that consists of only the prefix without its
+    /// trailing whitespace (e.g. a `|` for preformatted lines).
+    fn find_in_or_equals(&self, line: &str) -> Option<usize> {
+        self.find_in(line).or_else(|| {
+            std::iter::once(&self.canonical)
+                .chain(self.alternatives.iter())
+                .any(|prefix| !line.is_empty() && prefix.trim_end() == line)
+                .then_some(line.len())
+        })
+    }
+}
+
+/// The prefixes that mark the type of a line. The defaults match those of *vim-outlaw*, but it
+/// lets users change the topic mark (the prefix of headers).
+///
+/// The `[ ]`/`[x]` of TODO items and the markers of fenced filetype blocks are not configurable,
+/// since they are parsed as checkboxes and fences rather than as prefixes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Syntax {
+    pub header: Prefix,
+    pub bullet_point: Prefix,
+    pub preformatted: Prefix,
+    pub quote: Prefix,
+    /// Prefix of wrapped lines of a list item, which aligns them with the text of the item. Should
+    /// be whitespace, since wrapped lines are recognized by their indenting.
+    pub list_continuation: String,
+}
+
+impl Syntax {
+    /// Returns the type of a (trimmed) line and the length of the prefix that marks it
+    pub(crate) fn detect(&self, line: &str) -> (LineType, usize) {
+        if let Some(length) = self.header.find_in(line) {
+            (LineType::Header, length)
+        } else if let Some(length) = self.bullet_point.find_in(line) {
+            (LineType::ListBulletPoint, length)
+        } else if line.starts_with(consts::PREFIX_TODO_ITEM) {
+            (LineType::ListTodoItem, 0)
+        } else if line.starts_with(consts::MARKER_FENCED_FILETYPE_BACKTICK)
+            || line.starts_with(consts::MARKER_FENCED_FILETYPE_TILDE)
+        {
+            (LineType::FencedFiletypeMarker, 0)
+        } else if let Some(length) = self.preformatted.find_in_or_equals(line) {
+            (LineType::Preformatted, length)
+        } else if let Some(length) = self.quote.find_in(line) {
+            (LineType::Quote, length)
+        } else {
+            (LineType::Text, 0)
+        }
+    }
+
+    /// Returns the canonical prefix of the given type of line
+    pub(crate) fn prefix_of(&self, line_type: LineType) -> &str {
+        match line_type {
+            LineType::Header => &self.header.canonical,
+            LineType::ListBulletPoint => &self.bullet_point.canonical,
+            LineType::ListContinuousLine => &self.list_continuation,
+            LineType::ListTodoItem => consts::PREFIX_TODO_ITEM,
+            LineType::Preformatted => &self.preformatted.canonical,
+            LineType::Quote => &self.quote.canonical,
+            _ => "",
+        }
+    }
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax {
+            header: Prefix::new(consts::PREFIX_HEADER),
+            bullet_point: Prefix::new(consts::PREFIX_BULLET_POINT),
+            preformatted: Prefix::new(consts::PREFIX_PREFORMATTED),
+            quote: Prefix::new(consts::PREFIX_QUOTE),
+            list_continuation: consts::PREFIX_LIST_CONTINUATION.to_owned(),
+        }
+    }
+}
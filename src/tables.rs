@@ -0,0 +1,185 @@
+use unicode_width::UnicodeWidthStr;
+
+use crate::{FormattedLine, LineType};
+
+const CELL_SEPARATOR: char = '|';
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Alignment {
+    /// No alignment marker was given, which aligns the cell's contents to the left
+    Default,
+    Left,
+    Right,
+    Center,
+}
+
+enum Row {
+    Cells(Vec<String>),
+    /// The `|---|---|` line separating the table's header from its body
+    Separator(Vec<Alignment>),
+}
+
+/// Aligns the columns of tables, which are runs of two or more consecutive preformatted lines
+/// that start and end with a `|`, like `| name | owner | status |`. A table can contain a
+/// separator line (e.g. `|:---|---:|`) whose colons determine the alignment of each column.
+///
+/// Preformatted lines that are not part of a table are left untouched.
+pub fn align_tables(formatted_lines: &mut [FormattedLine]) {
+    let mut index = 0;
+    while index < formatted_lines.len() {
+        let table_length = formatted_lines[index..]
+            .iter()
+            .take_while(|line| {
+                is_table_row(line) && line.indent_level == formatted_lines[index].indent_level
+            })
+            .count();
+
+        if table_length >= 2 {
+            align_table(&mut formatted_lines[index..index + table_length]);
+            index += table_length;
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Returns whether `line` is a row of a table, including the separator line (`|---|---|`)
+pub(crate) fn is_table_row(line: &FormattedLine) -> bool {
+    let is_row = line.contents.len() >= 2
+        && line.contents.starts_with(CELL_SEPARATOR)
+        && line.contents.ends_with(CELL_SEPARATOR);
+
+    match line.line_type {
+        LineType::Preformatted => is_row,
+        // A separator written without spaces (`|---|`) lacks the prefix of a preformatted line
+        LineType::Text => is_row && matches!(parse_row(&line.contents), Row::Separator(_)),
+        _ => false,
+    }
+}
+
+/// Returns whether `line` is the `|---|---|` line separating the header of a table from its body
+pub(crate) fn is_table_separator(line: &FormattedLine) -> bool {
+    is_table_row(line) && matches!(parse_row(&line.contents), Row::Separator(_))
+}
+
+fn align_table(table_lines: &mut [FormattedLine]) {
+    let rows: Vec<Row> = table_lines
+        .iter()
+        .map(|line| parse_row(&line.contents))
+        .collect();
+
+    // Separators without any rows of cells, like two `|---|` lines, aren't a table
+    if !rows.iter().any(|row| matches!(row, Row::Cells(_))) {
+        return;
+    }
+
+    let num_columns = rows
+        .iter()
+        .map(|row| match row {
+            Row::Cells(cells) => cells.len(),
+            Row::Separator(alignments) => alignments.len(),
+        })
+        .max()
+        .unwrap_or(0);
+
+    let mut alignments = vec![Alignment::Default; num_columns];
+    // A separator needs at least 3 dashes (or a colon and 2 dashes) per column
+    let mut column_widths = vec![3; num_columns];
+    for row in rows.iter() {
+        match row {
+            Row::Cells(cells) => {
+                for (column, cell) in cells.iter().enumerate() {
+                    column_widths[column] = column_widths[column].max(cell.width());
+                }
+            }
+            Row::Separator(separator_alignments) => {
+                alignments[..separator_alignments.len()].copy_from_slice(separator_alignments);
+            }
+        }
+    }
+
+    for (line, row) in table_lines.iter_mut().zip(rows) {
+        line.contents = match row {
+            Row::Cells(cells) => format_cells(&cells, &column_widths, &alignments),
+            Row::Separator(_) => format_separator(&column_widths, &alignments),
+        };
+        line.line_type = LineType::Preformatted;
+    }
+}
+
+fn parse_row(contents: &str) -> Row {
+    let cells: Vec<&str> = contents[1..contents.len() - 1]
+        .split(CELL_SEPARATOR)
+        .map(str::trim)
+        .collect();
+
+    if cells.iter().all(|cell| is_separator_cell(cell)) {
+        Row::Separator(cells.into_iter().map(parse_alignment).collect())
+    } else {
+        Row::Cells(cells.into_iter().map(str::to_owned).collect())
+    }
+}
+
+fn is_separator_cell(cell: &str) -> bool {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-') && cell.len() - dashes.len() <= 2
+}
+
+fn parse_alignment(cell: &str) -> Alignment {
+    match (cell.starts_with(':'), cell.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (true, false) => Alignment::Left,
+        (false, true) => Alignment::Right,
+        (false, false) => Alignment::Default,
+    }
+}
+
+fn format_cells(cells: &[String], column_widths: &[usize], alignments: &[Alignment]) -> String {
+    let mut formatted = String::from(CELL_SEPARATOR);
+
+    for (column, (&width, &alignment)) in column_widths.iter().zip(alignments).enumerate() {
+        let cell = cells.get(column).map(String::as_str).unwrap_or("");
+        let padding = width - cell.width();
+
+        let (padding_left, padding_right) = match alignment {
+            Alignment::Default | Alignment::Left => (0, padding),
+            Alignment::Right => (padding, 0),
+            Alignment::Center => (padding / 2, padding - padding / 2),
+        };
+
+        formatted += &format!(
+            " {}{}{} {}",
+            " ".repeat(padding_left),
+            cell,
+            " ".repeat(padding_right),
+            CELL_SEPARATOR
+        );
+    }
+
+    formatted
+}
+
+fn format_separator(column_widths: &[usize], alignments: &[Alignment]) -> String {
+    let mut formatted = String::from(CELL_SEPARATOR);
+
+    for (&width, &alignment) in column_widths.iter().zip(alignments) {
+        let (colon_left, colon_right) = match alignment {
+            Alignment::Default => ("", ""),
+            Alignment::Left => (":", ""),
+            Alignment::Right => ("", ":"),
+            Alignment::Center => (":", ":"),
+        };
+        let num_dashes = width + 2 - colon_left.len() - colon_right.len();
+
+        formatted += &format!(
+            "{}{}{}{}",
+            colon_left,
+            "-".repeat(num_dashes),
+            colon_right,
+            CELL_SEPARATOR
+        );
+    }
+
+    formatted
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+
+use crate::{Block, Document, FormattedLine, LineEnding, LineType, RawLine, Syntax};
+
+/// A document as a tree of sections, where every header is a section that contains the sections
+/// of the headers nested under it.
+///
+/// The text above the first header is the body of the `root` section, which doesn't have a
+/// title.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tree {
+    pub root: Section,
+    /// The prefixes that are used for the headers when turning the tree back into a `Document`
+    pub syntax: Syntax,
+    pub line_ending: LineEnding,
+    pub has_byte_order_mark: bool,
+}
+
+impl Tree {
+    pub fn from_document(document: &Document) -> Self {
+        Self::from_document_with_syntax(document, &Syntax::default())
+    }
+
+    /// Like `from_document()`, but uses the header prefixes of `syntax` to find the titles of the
+    /// headers.
+    pub fn from_document_with_syntax(document: &Document, syntax: &Syntax) -> Self {
+        let root = Section {
+            body: relative_body(
+                document
+                    .blocks
+                    .first()
+                    .expect("there should always be at least one Block"),
+            ),
+            ..Section::default()
+        };
+
+        // The sections that can still get subsections, from the root down to the last section
+        let mut open_sections = vec![root];
+        for block in document.blocks.iter().filter(|block| block.has_header()) {
+            let depth = block.header.indent_level + 1;
+            while open_sections.len() > depth {
+                close_last_section(&mut open_sections);
+            }
+
+            open_sections.push(Section {
+                title: Some(header_title(&block.header, syntax)),
+                body: relative_body(block),
+                subsections: Vec::new(),
+            });
+        }
+
+        while open_sections.len() > 1 {
+            close_last_section(&mut open_sections);
+        }
+
+        Tree {
+            root: open_sections.remove(0),
+            syntax: syntax.clone(),
+            line_ending: document.line_ending,
+            has_byte_order_mark: document.has_byte_order_mark,
+        }
+    }
+
+    /// Turns the tree into a `Document` that can be formatted with `format_to_string()`
+    pub fn to_document(&self) -> Document {
+        let mut document = Document::new();
+        document.line_ending = self.line_ending;
+        document.has_byte_order_mark = self.has_byte_order_mark;
+
+        document.last_block_mut().contents = absolute_body(&self.root.body, 0);
+        for node in self.root().pre_order().skip(1) {
+            let depth = node.depth();
+            let contents = format!(
+                "{}{}",
+                self.syntax.header.canonical,
+                node.title().unwrap_or_default()
+            );
+            let header = FormattedLine {
+                contents,
+                indent_level: depth - 1,
+                line_type: LineType::Header,
+                original_raw: RawLine::default(),
+            };
+
+            let mut block = Block::new(header);
+            block.contents = absolute_body(node.body(), depth);
+            document.add_block(block);
+        }
+
+        document
+    }
+
+    pub fn root(&self) -> Node<'_> {
+        Node {
+            root: &self.root,
+            section: &self.root,
+            path: Vec::new(),
+        }
+    }
+
+    /// Returns the section at `path` (see `Node::path()`), where an empty path is the root
+    pub fn section(&self, path: &[usize]) -> Option<&Section> {
+        path.iter()
+            .try_fold(&self.root, |section, &index| section.subsections.get(index))
+    }
+
+    pub fn section_mut(&mut self, path: &[usize]) -> Option<&mut Section> {
+        path.iter().try_fold(&mut self.root, |section, &index| {
+            section.subsections.get_mut(index)
+        })
+    }
+
+    /// Inserts a section with a header with `title` at `path`, moving the section that was at that
+    /// position (and the ones after it) down. The last index of the path can be the number of
+    /// subsections to add it after the others.
+    pub fn insert_section(&mut self, path: &[usize], title: &str) -> Result<(), EditError> {
+        self.insert(path, Section::new(title))
+    }
+
+    /// Adds text to the end of the body of the section at `path`, every line of `text` becomes a
+    /// line of the body. Fails without adding anything when a line would be read back as a header,
+    /// list item or fence marker, like `* not a list`.
+    pub fn append_text(&mut self, path: &[usize], text: &str) -> Result<(), EditError> {
+        let syntax = self.syntax.clone();
+        self.existing_section_mut(path)?.push_text(text, &syntax)
+    }
+
+    /// Adds a bullet point to the end of the body of the section at `path`. The lines of `text` are
+    /// joined into one.
+    pub fn append_list_item(&mut self, path: &[usize], text: &str) -> Result<(), EditError> {
+        let syntax = self.syntax.clone();
+        self.existing_section_mut(path)?
+            .push_list_item(text, &syntax);
+
+        Ok(())
+    }
+
+    /// Adds a TODO item to the end of the body of the section at `path`. The lines of `text` are
+    /// joined into one.
+    pub fn append_todo_item(
+        &mut self,
+        path: &[usize],
+        text: &str,
+        is_checked: bool,
+    ) -> Result<(), EditError> {
+        self.existing_section_mut(path)?
+            .push_todo_item(text, is_checked);
+
+        Ok(())
+    }
+
+    /// Removes the section at `path`, together with the sections nested under it
+    pub fn delete_section(&mut self, path: &[usize]) -> Result<Section, EditError> {
+        let (&index, parent_path) = path
+            .split_last()
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))?;
+        let parent = self
+            .section_mut(parent_path)
+            .filter(|parent| index < parent.subsections.len())
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))?;
+
+        Ok(parent.subsections.remove(index))
+    }
+
+    /// Moves the section at `from` (and the sections nested under it) to `to`, which is a path in
+    /// the tree after the section has been removed from `from`
+    pub fn move_section(&mut self, from: &[usize], to: &[usize]) -> Result<(), EditError> {
+        let section = self.delete_section(from)?;
+
+        match self.insertion_point(to) {
+            Ok((parent, index)) => {
+                parent.subsections.insert(index, section);
+                Ok(())
+            }
+            Err(error) => {
+                // Put the section back where it was
+                let (parent, index) = self
+                    .insertion_point(from)
+                    .expect("the section was just removed from there");
+                parent.subsections.insert(index, section);
+
+                Err(error)
+            }
+        }
+    }
+
+    /// Moves the section at `path` one level up, directly after its parent
+    pub fn promote_section(&mut self, path: &[usize]) -> Result<(), EditError> {
+        let [parent_path @ .., parent_index, _] = path else {
+            return Err(EditError::CannotPromote(path.to_vec()));
+        };
+        let to = [parent_path, &[parent_index + 1]].concat();
+
+        self.move_section(path, &to)
+    }
+
+    /// Moves the section at `path` one level down, to the end of the subsections of the section
+    /// before it
+    pub fn demote_section(&mut self, path: &[usize]) -> Result<(), EditError> {
+        let [parent_path @ .., index] = path else {
+            return Err(EditError::CannotDemote(path.to_vec()));
+        };
+        if *index == 0 {
+            return Err(EditError::CannotDemote(path.to_vec()));
+        }
+
+        let previous_section = self
+            .section(&[parent_path, &[index - 1]].concat())
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))?;
+        let to = [
+            parent_path,
+            &[index - 1, previous_section.subsections.len()],
+        ]
+        .concat();
+
+        self.move_section(path, &to)
+    }
+
+    fn existing_section_mut(&mut self, path: &[usize]) -> Result<&mut Section, EditError> {
+        self.section_mut(path)
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))
+    }
+
+    fn insert(&mut self, path: &[usize], section: Section) -> Result<(), EditError> {
+        let (parent, index) = self.insertion_point(path)?;
+        parent.subsections.insert(index, section);
+
+        Ok(())
+    }
+
+    /// Returns the section a section at `path` would be a subsection of, and its index in there
+    fn insertion_point(&mut self, path: &[usize]) -> Result<(&mut Section, usize), EditError> {
+        let (&index, parent_path) = path
+            .split_last()
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))?;
+        let parent = self
+            .section_mut(parent_path)
+            .filter(|parent| index <= parent.subsections.len())
+            .ok_or_else(|| EditError::NoSuchSection(path.to_vec()))?;
+
+        Ok((parent, index))
+    }
+}
+
+/// The reason an edit of a `Tree` could not be made
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum EditError {
+    /// There is no section at the path, or a section can't be inserted there
+    NoSuchSection(Vec<usize>),
+    /// Top level sections can't be promoted
+    CannotPromote(Vec<usize>),
+    /// The first subsection of a section can't be demoted, since there is no section before it
+    /// to move it into
+    CannotDemote(Vec<usize>),
+    /// The line of text would be read as a header, list item or fence marker instead
+    NotText(String),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchSection(path) => write!(f, "there is no section at {:?}", path),
+            Self::CannotPromote(path) => write!(f, "the section at {:?} can't be promoted", path),
+            Self::CannotDemote(path) => write!(f, "the section at {:?} can't be demoted", path),
+            Self::NotText(line) => write!(f, "{:?} can't be written as a line of text", line),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// A header with the lines below it, and the sections of the headers that are nested under it
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Section {
+    title: Option<String>,
+    body: Vec<FormattedLine>,
+    subsections: Vec<Section>,
+}
+
+impl Section {
+    /// Creates a section with a header with `title`, where the lines of `title` are joined into
+    /// one, since a header only has a single line
+    pub fn new(title: &str) -> Self {
+        Section {
+            title: Some(single_line(title)),
+            ..Section::default()
+        }
+    }
+
+    /// The text of the header without its prefix, or `None` for the root of a tree
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The lines between the header and the first subsection. Their indent levels are relative to
+    /// the section, so the lines that are directly part of it have an indent level of 0.
+    pub fn body(&self) -> &[FormattedLine] {
+        &self.body
+    }
+
+    pub fn subsections(&self) -> &[Section] {
+        &self.subsections
+    }
+
+    /// Adds every line of `text` to the body. A blank line is added before the text when it
+    /// follows a list, otherwise it would become part of the last list item.
+    ///
+    /// Quotes and preformatted lines keep their type. A line that would be read as a header, list
+    /// item or fence marker can't be written as text, so nothing is added when there is one.
+    pub(crate) fn push_text(&mut self, text: &str, syntax: &Syntax) -> Result<(), EditError> {
+        let lines = text
+            .trim()
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                match syntax.detect(line).0 {
+                    _ if line.is_empty() => Ok((LineType::Text, line)),
+                    line_type @ (LineType::Text | LineType::Quote | LineType::Preformatted) => {
+                        Ok((line_type, line))
+                    }
+                    _ => Err(EditError::NotText(line.to_owned())),
+                }
+            })
+            .collect::<Result<Vec<(LineType, &str)>, EditError>>()?;
+
+        if self.body.last().map_or(false, FormattedLine::is_list_item) {
+            self.body.push(FormattedLine::empty());
+        }
+
+        for (line_type, line) in lines {
+            if line.is_empty() {
+                self.body.push(FormattedLine::empty());
+            } else {
+                self.push_line(line_type, line.to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn push_list_item(&mut self, text: &str, syntax: &Syntax) {
+        let contents = format!("{}{}", syntax.bullet_point.canonical, single_line(text));
+
+        self.push_line(LineType::ListBulletPoint, contents);
+    }
+
+    pub(crate) fn push_todo_item(&mut self, text: &str, is_checked: bool) {
+        let contents = format!(
+            "[{}] {}",
+            if is_checked { 'x' } else { ' ' },
+            single_line(text)
+        );
+
+        self.push_line(LineType::ListTodoItem, contents);
+    }
+
+    pub(crate) fn push_section(&mut self, section: Section) {
+        self.subsections.push(section);
+    }
+
+    fn push_line(&mut self, line_type: LineType, contents: String) {
+        self.body.push(FormattedLine {
+            original_raw: RawLine::default(),
+            contents,
+            indent_level: 0,
+            line_type,
+        });
+    }
+}
+
+/// A section at a certain position in a `Tree`, which knows how to get to its parent
+#[derive(Debug, Clone)]
+pub struct Node<'a> {
+    root: &'a Section,
+    section: &'a Section,
+    /// The index of the section in the subsections of every section on the way from the root
+    path: Vec<usize>,
+}
+
+impl<'a> Node<'a> {
+    pub fn section(&self) -> &'a Section {
+        self.section
+    }
+
+    pub fn title(&self) -> Option<&'a str> {
+        self.section.title()
+    }
+
+    pub fn body(&self) -> &'a [FormattedLine] {
+        self.section.body()
+    }
+
+    /// The number of headers above and including this one, which is 0 for the root
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// The indexes that lead from the root to this section, e.g. `[1, 0]` for the first
+    /// subsection of the second top level section
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + '_ {
+        self.section
+            .subsections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| Node {
+                root: self.root,
+                section,
+                path: [self.path.as_slice(), &[index]].concat(),
+            })
+    }
+
+    pub fn parent(&self) -> Option<Node<'a>> {
+        let (_, parent_path) = self.path.split_last()?;
+        let section = parent_path
+            .iter()
+            .fold(self.root, |section, &index| &section.subsections[index]);
+
+        Some(Node {
+            root: self.root,
+            section,
+            path: parent_path.to_vec(),
+        })
+    }
+
+    /// Iterates over this section and all sections nested under it, each section before its
+    /// subsections (pre-order)
+    pub fn pre_order(&self) -> PreOrder<'a> {
+        PreOrder {
+            stack: vec![self.clone()],
+        }
+    }
+}
+
+/// Iterator over the sections of a tree in pre-order, see `Node::pre_order()`
+#[derive(Debug)]
+pub struct PreOrder<'a> {
+    stack: Vec<Node<'a>>,
+}
+
+impl<'a> Iterator for PreOrder<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let children = node.children().collect::<Vec<_>>();
+        self.stack.extend(children.into_iter().rev());
+
+        Some(node)
+    }
+}
+
+fn close_last_section(open_sections: &mut Vec<Section>) {
+    let section = open_sections
+        .pop()
+        .expect("there should be a section to close");
+    open_sections
+        .last_mut()
+        .expect("the root should never be closed")
+        .subsections
+        .push(section);
+}
+
+fn header_title(header: &FormattedLine, syntax: &Syntax) -> String {
+    let prefix_length = header.line_type.get_prefix_length(syntax);
+
+    header
+        .contents
+        .get(prefix_length..)
+        .unwrap_or("")
+        .to_owned()
+}
+
+fn relative_body(block: &Block) -> Vec<FormattedLine> {
+    let base_indent_level = block.contents_indent_level();
+
+    block
+        .contents
+        .iter()
+        .map(|line| FormattedLine {
+            indent_level: line.indent_level.saturating_sub(base_indent_level),
+            ..line.clone()
+        })
+        .collect()
+}
+
+fn absolute_body(body: &[FormattedLine], base_indent_level: usize) -> Vec<FormattedLine> {
+    body.iter()
+        .map(|line| FormattedLine {
+            indent_level: line.indent_level + base_indent_level,
+            ..line.clone()
+        })
+        .collect()
+}
+
+/// Joins the lines of a text that has to fit on a single line, like the title of a header
+pub(crate) fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
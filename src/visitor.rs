@@ -0,0 +1,121 @@
+use crate::{Document, FormattedLine, LineType, Syntax};
+
+/// Hooks that are called by `walk()` for the parts of a document, in the order they appear in the
+/// document. All hooks do nothing by default, so a visitor only has to implement the ones it
+/// needs.
+///
+/// Lines are passed with their prefix (e.g. `* ` for bullet points) and their indent level, like
+/// they are formatted.
+#[allow(unused_variables)]
+pub trait Visitor {
+    fn visit_header(&mut self, header: &FormattedLine) {}
+
+    /// A line of text that is not part of a list
+    fn visit_text(&mut self, line: &FormattedLine) {}
+
+    fn visit_blank_line(&mut self, line: &FormattedLine) {}
+
+    /// A bullet point, followed by the lines it was wrapped onto
+    fn visit_list_item(&mut self, lines: &[FormattedLine]) {}
+
+    /// A TODO item, followed by the lines it was wrapped onto
+    fn visit_todo(&mut self, lines: &[FormattedLine], is_checked: bool) {}
+
+    /// The lines of a fenced filetype block, including the lines with the opening and closing
+    /// markers. A block that was never closed has no closing marker, and a block that continues
+    /// after a header has no opening marker in the block after the header.
+    fn visit_fenced_block(&mut self, lines: &[FormattedLine]) {}
+
+    fn visit_preformatted(&mut self, line: &FormattedLine) {}
+
+    fn visit_quote(&mut self, line: &FormattedLine) {}
+}
+
+/// Calls the hooks of `visitor` for every header and line of `document`
+pub fn walk(document: &Document, visitor: &mut impl Visitor) {
+    for block in document.blocks.iter() {
+        if let Some(header) = block.header() {
+            visitor.visit_header(header);
+        }
+
+        let lines = block.contents();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = &lines[index];
+            let num_lines = match line.line_type {
+                LineType::ListBulletPoint => {
+                    let num_lines = 1 + num_continuation_lines(&lines[index..]);
+                    visitor.visit_list_item(&lines[index..index + num_lines]);
+                    num_lines
+                }
+                LineType::ListTodoItem => {
+                    let num_lines = 1 + num_continuation_lines(&lines[index..]);
+                    visitor.visit_todo(
+                        &lines[index..index + num_lines],
+                        line.is_checked_todo_item(),
+                    );
+                    num_lines
+                }
+                LineType::FencedFiletypeMarker | LineType::FencedFiletype => {
+                    let num_lines = fenced_block_length(&lines[index..]);
+                    visitor.visit_fenced_block(&lines[index..index + num_lines]);
+                    num_lines
+                }
+                LineType::Preformatted => {
+                    visitor.visit_preformatted(line);
+                    1
+                }
+                LineType::Quote => {
+                    visitor.visit_quote(line);
+                    1
+                }
+                _ if line.is_empty() => {
+                    visitor.visit_blank_line(line);
+                    1
+                }
+                // A continuation line without a list item before it can only be the result of
+                // editing the lines, so it is treated like normal text
+                LineType::Text | LineType::ListContinuousLine | LineType::Header => {
+                    visitor.visit_text(line);
+                    1
+                }
+            };
+
+            index += num_lines;
+        }
+    }
+}
+
+/// Returns the text of a line without its prefix, e.g. `* ` for bullet points
+pub(crate) fn without_prefix<'l>(line: &'l FormattedLine, syntax: &Syntax) -> &'l str {
+    line.contents()
+        .get(line.line_type().get_prefix_length(syntax)..)
+        .unwrap_or("")
+}
+
+/// Returns the number of lines that a list item (the first of `lines`) was wrapped onto
+fn num_continuation_lines(lines: &[FormattedLine]) -> usize {
+    lines[1..]
+        .iter()
+        .take_while(|line| {
+            line.line_type == LineType::ListContinuousLine
+                && line.indent_level == lines[0].indent_level
+        })
+        .count()
+}
+
+/// Returns the number of lines of the fenced filetype block that starts with the first of `lines`
+fn fenced_block_length(lines: &[FormattedLine]) -> usize {
+    let starts_with_marker = lines[0].line_type == LineType::FencedFiletypeMarker;
+    let num_body_lines = lines
+        .iter()
+        .skip(usize::from(starts_with_marker))
+        .take_while(|line| line.line_type == LineType::FencedFiletype)
+        .count();
+    let length = usize::from(starts_with_marker) + num_body_lines;
+
+    match lines.get(length) {
+        Some(line) if line.line_type == LineType::FencedFiletypeMarker => length + 1,
+        _ => length,
+    }
+}